@@ -0,0 +1,133 @@
+use super::*;
+use serde_json::json;
+use solver::Solver;
+use std::io::{self, BufRead, Write};
+use std::time::Instant;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Solve,
+    Analyse,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    // "pos score nodes time_us" like the reference solver
+    Text,
+    Csv,
+    JsonLines,
+}
+
+enum Outcome {
+    Score(isize),
+    Scores(Vec<Option<isize>>),
+    Invalid,
+}
+
+struct Record<'a> {
+    pos: &'a str,
+    outcome: Outcome,
+    nodes: u64,
+    time_us: u128,
+}
+
+impl Record<'_> {
+    fn write(&self, out: &mut impl Write, format: Format) -> io::Result<()> {
+        let scores = |sep: &str| match &self.outcome {
+            Outcome::Score(score) => score.to_string(),
+            Outcome::Scores(scores) => scores
+                .iter()
+                .map(|s| s.map_or("-".to_string(), |s| s.to_string()))
+                .collect::<Vec<_>>()
+                .join(sep),
+            Outcome::Invalid => String::new(),
+        };
+        match (format, &self.outcome) {
+            (Format::Text, Outcome::Invalid) => writeln!(out, "{} invalid", self.pos),
+            (Format::Text, _) => writeln!(
+                out,
+                "{} {} {} {}",
+                self.pos,
+                scores(" "),
+                self.nodes,
+                self.time_us
+            ),
+            (Format::Csv, Outcome::Invalid) => {
+                writeln!(out, "{},,,,invalid position", csv_field(self.pos))
+            }
+            (Format::Csv, _) => writeln!(
+                out,
+                "{},{},{},{},",
+                self.pos,
+                scores(";"),
+                self.nodes,
+                self.time_us
+            ),
+            (Format::JsonLines, outcome) => {
+                let value = match outcome {
+                    Outcome::Score(score) => json!({
+                        "pos": self.pos,
+                        "score": score,
+                        "nodes": self.nodes,
+                        "time_us": self.time_us,
+                    }),
+                    Outcome::Scores(scores) => json!({
+                        "pos": self.pos,
+                        "scores": scores,
+                        "nodes": self.nodes,
+                        "time_us": self.time_us,
+                    }),
+                    Outcome::Invalid => json!({ "pos": self.pos, "error": "invalid position" }),
+                };
+                writeln!(out, "{value}")
+            }
+        }
+    }
+}
+
+// invalid lines are echoed back, quote them in case they contain separators
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+// one move sequence per line, results are flushed line by line
+// so the output can be consumed while the input is still streaming
+pub fn run(
+    solver: &mut Solver,
+    input: impl BufRead,
+    mut output: impl Write,
+    mode: Mode,
+    format: Format,
+    weak: bool,
+) -> io::Result<()> {
+    if format == Format::Csv {
+        writeln!(output, "pos,score,nodes,time_us,error")?;
+    }
+    for line in input.lines() {
+        let line = line?;
+        let moves = line.trim();
+        if moves.is_empty() {
+            continue;
+        }
+        let before = Instant::now();
+        solver.reset();
+        let outcome = match (Position::try_parse(moves), mode) {
+            (None, _) => Outcome::Invalid,
+            (Some(pos), Mode::Solve) => Outcome::Score(solver.solve(&pos, weak)),
+            (Some(pos), Mode::Analyse) => Outcome::Scores(solver.analyse(&pos, weak)),
+        };
+        let record = Record {
+            pos: moves,
+            outcome,
+            nodes: solver.node_count,
+            time_us: before.elapsed().as_micros(),
+        };
+        record.write(&mut output, format)?;
+        output.flush()?;
+    }
+    Ok(())
+}
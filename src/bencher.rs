@@ -1,6 +1,9 @@
 use super::*;
+use dataset::{Dataset, DatasetError};
 use position::OpeningBook;
 use solver::Solver;
+use std::error::Error;
+use std::fmt;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::Error as IOError;
@@ -14,48 +17,491 @@ fn read_file_to_string(file_path: &str) -> Result<String, IOError> {
     Ok(s)
 }
 
-pub fn run(file_path: &str, weak: bool) {
-    let book = Arc::new(OpeningBook::load("7x6.book").expect("book to load"));
-    let mut solver = Solver::with_opening_book(Arc::clone(&book));
+#[derive(Debug)]
+pub enum BenchError {
+    Io(IOError),
+    // 1-based line number and what is wrong with it
+    Parse(usize, String),
+    // baseline file and what is wrong with it
+    Baseline(String, String),
+}
+impl fmt::Display for BenchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "couldn't read file: {e}"),
+            Self::Parse(n, msg) => write!(f, "line {n}: {msg}"),
+            Self::Baseline(path, msg) => write!(f, "{path}: {msg}"),
+        }
+    }
+}
+impl Error for BenchError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Parse(..) | Self::Baseline(..) => None,
+        }
+    }
+}
+impl From<IOError> for BenchError {
+    fn from(e: IOError) -> Self {
+        Self::Io(e)
+    }
+}
+impl From<DatasetError> for BenchError {
+    fn from(e: DatasetError) -> Self {
+        match e {
+            DatasetError::Io(e) => Self::Io(e),
+            DatasetError::Parse(n, msg) => Self::Parse(n, msg),
+        }
+    }
+}
 
-    if let Ok(s) = read_file_to_string(file_path) {
-        let num_lines = s.lines().count();
-        let res = s
-            .lines()
-            .flat_map(|line| {
-                let vars = line.split_whitespace().collect::<Vec<_>>();
-                vars.get(1)
-                    .and_then(|numstr| numstr.parse().ok())
-                    .map(|num: isize| (vars[0], num))
-            })
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BenchSample {
+    pub moves: String,
+    pub expected: isize,
+    pub score: isize,
+    pub nodes: u64,
+    pub time: Duration,
+    // weak runs only compare the sign of the scores
+    pub passed: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BenchReport {
+    pub samples: Vec<BenchSample>,
+    // malformed lines of the dataset left out, 1-based line number and error
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub skipped: Vec<(usize, String)>,
+}
+impl BenchReport {
+    pub fn count(&self) -> usize {
+        self.samples.len()
+    }
+    pub fn failures(&self) -> impl Iterator<Item = &BenchSample> {
+        self.samples.iter().filter(|s| !s.passed)
+    }
+    pub fn passed(&self) -> bool {
+        self.failures().next().is_none() && self.skipped.is_empty()
+    }
+    pub fn total_nodes(&self) -> u64 {
+        self.samples.iter().map(|s| s.nodes).sum()
+    }
+    pub fn total_time(&self) -> Duration {
+        self.samples.iter().map(|s| s.time).sum()
+    }
+    pub fn mean_nodes(&self) -> f64 {
+        self.total_nodes() as f64 / std::cmp::max(1, self.count()) as f64
+    }
+    pub fn mean_time(&self) -> Duration {
+        self.total_time() / std::cmp::max(1, self.count()) as u32
+    }
+    pub fn kpos_per_sec(&self) -> f64 {
+        self.total_nodes() as f64 / self.total_time().as_secs_f64().max(f64::EPSILON) / 1000.0
+    }
+    pub fn node_distribution(&self) -> Option<Distribution<u64>> {
+        Distribution::new(self.samples.iter().map(|s| s.nodes).collect())
+    }
+    pub fn time_distribution(&self) -> Option<Distribution<Duration>> {
+        Distribution::new(self.samples.iter().map(|s| s.time).collect())
+    }
+    pub fn slowest(&self, n: usize) -> Vec<&BenchSample> {
+        let mut samples: Vec<_> = self.samples.iter().collect();
+        samples.sort_by_key(|s| std::cmp::Reverse(s.time));
+        samples.truncate(n);
+        samples
+    }
+    // (lower bound in µs, count) for power of two time buckets, from the
+    // fastest to the slowest sample
+    pub fn histogram(&self) -> Vec<(u64, usize)> {
+        let bucket = |s: &BenchSample| (s.time.as_micros() as u64).max(1).ilog2() as usize;
+        let mut counts = vec![0; self.samples.iter().map(bucket).max().map_or(0, |b| b + 1)];
+        self.samples.iter().for_each(|s| counts[bucket(s)] += 1);
+        let first = counts.iter().position(|&c| c > 0).unwrap_or(0);
+        counts
+            .into_iter()
             .enumerate()
-            .map(|(i, (s, num))| {
-                let pos = Position::parse(s);
-                solver.reset();
-                let before = Instant::now();
-                let sol = solver.solve(&pos, weak);
-                let elapsed: Duration = before.elapsed();
-                println!(
-                    "progress ... {:.2}%",
-                    (i as f64 / num_lines as f64) * 100_f64
+            .skip(first)
+            .map(|(i, count)| (1 << i, count))
+            .collect()
+    }
+    pub fn format_stats(&self, slowest: usize) -> String {
+        let mut s = String::new();
+        if let Some(d) = self.node_distribution() {
+            s += &format!(
+                "nodes: min {}, median {}, p90 {}, p99 {}, max {}\n",
+                d.min, d.median, d.p90, d.p99, d.max
+            );
+        }
+        if let Some(d) = self.time_distribution() {
+            s += &format!(
+                "time: min {:.2?}, median {:.2?}, p90 {:.2?}, p99 {:.2?}, max {:.2?}\n",
+                d.min, d.median, d.p90, d.p99, d.max
+            );
+        }
+        if slowest > 0 {
+            s += "slowest:\n";
+            for sample in self.slowest(slowest) {
+                s += &format!(
+                    "  {} {:.2?} {} nodes\n",
+                    sample.moves, sample.time, sample.nodes
                 );
-                assert_eq!(sol, num);
-                (solver.node_count, elapsed)
-            })
-            .fold((0, 0, Duration::ZERO), |acc, (nb, dur)| {
-                (acc.0 + 1, acc.1 + nb, acc.2 + dur)
+            }
+        }
+        let histogram = self.histogram();
+        let top = histogram.iter().map(|&(_, c)| c).max().unwrap_or(0);
+        if !histogram.is_empty() {
+            s += "time histogram:\n";
+        }
+        for (us, count) in histogram {
+            let bar = "#".repeat((count * 40).div_ceil(std::cmp::max(1, top)));
+            s += &format!("  >= {:>9}µs {:>6} {bar}\n", us, count);
+        }
+        s
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Distribution<T> {
+    pub min: T,
+    pub median: T,
+    pub p90: T,
+    pub p99: T,
+    pub max: T,
+}
+impl<T: Copy + Ord> Distribution<T> {
+    // nearest rank percentiles, None without samples
+    pub fn new(mut samples: Vec<T>) -> Option<Self> {
+        samples.sort();
+        let rank = |p: usize| samples[(samples.len() * p).div_ceil(100).saturating_sub(1)];
+        Some(Self {
+            min: *samples.first()?,
+            median: rank(50),
+            p90: rank(90),
+            p99: rank(99),
+            max: *samples.last()?,
+        })
+    }
+}
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "count: {}, mean nb pos: {}, mean time: {:.2?}, K pos / s: {:.0}",
+            self.count(),
+            self.mean_nodes(),
+            self.mean_time(),
+            self.kpos_per_sec(),
+        )?;
+        let failures: Vec<_> = self.failures().collect();
+        if !failures.is_empty() {
+            write!(f, "\n{} of {} failed:", failures.len(), self.count())?;
+            for s in failures {
+                write!(
+                    f,
+                    "\n  {} expected: {}, got: {}",
+                    s.moves, s.expected, s.score
+                )?;
+            }
+        }
+        if !self.skipped.is_empty() {
+            write!(f, "\n{} lines skipped:", self.skipped.len())?;
+            for (n, msg) in &self.skipped {
+                write!(f, "\n  line {n}: {msg}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// table_size as in Solver::with_table_size
+pub fn run(
+    file_path: &str,
+    book: Arc<OpeningBook>,
+    table_size: usize,
+    weak: bool,
+) -> Result<BenchReport, BenchError> {
+    let mut solver = Solver::with_table_size(book, table_size);
+
+    // bad lines are reported and left out instead of ending the run
+    let (dataset, errors) = Dataset::parse_lenient(&read_file_to_string(file_path)?);
+    let mut skipped = Vec::new();
+    for e in errors {
+        match e {
+            DatasetError::Io(e) => return Err(e.into()),
+            DatasetError::Parse(n, msg) => {
+                eprintln!("{file_path}:{n}: {msg}");
+                skipped.push((n, msg));
+            }
+        }
+    }
+    let entries = dataset.entries;
+    let num_lines = entries.len();
+    let samples = entries
+        .into_iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            solver.reset();
+            let before = Instant::now();
+            let sol = solver.solve(&entry.position, weak);
+            let elapsed: Duration = before.elapsed();
+            eprint!(
+                "\rprogress ... {:.2}%",
+                ((i + 1) as f64 / num_lines as f64) * 100_f64
+            );
+            let (expected, passed) = if weak {
+                (
+                    entry.weak_score(),
+                    sol.signum() == entry.weak_score().signum(),
+                )
+            } else {
+                (entry.score, sol == entry.score)
+            };
+            BenchSample {
+                moves: entry.moves,
+                expected,
+                score: sol,
+                nodes: solver.node_count,
+                time: elapsed,
+                passed,
+            }
+        })
+        .collect();
+    eprintln!();
+    Ok(BenchReport { samples, skipped })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SuiteResult {
+    pub dataset: String,
+    pub weak: bool,
+    pub report: BenchReport,
+}
+
+// `*` and `?` wildcards over a file name
+fn wildcard(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.split_first(), name.split_first()) {
+        (None, None) => true,
+        (Some((b'*', rest)), _) => {
+            wildcard(rest, name) || (!name.is_empty() && wildcard(pattern, &name[1..]))
+        }
+        (Some((b'?', rest)), Some((_, name))) => wildcard(rest, name),
+        (Some((p, rest)), Some((n, name))) if p == n => wildcard(rest, name),
+        _ => false,
+    }
+}
+
+// files of dir whose name matches pattern, sorted by name
+pub fn find_datasets(dir: &str, pattern: &str) -> Result<Vec<String>, BenchError> {
+    let mut files = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| wildcard(pattern.as_bytes(), name.as_bytes()))
+        })
+        .flat_map(|path| path.to_str().map(String::from))
+        .collect::<Vec<_>>();
+    files.sort();
+    Ok(files)
+}
+
+pub fn run_suite(
+    files: &[String],
+    book: Arc<OpeningBook>,
+    table_size: usize,
+    modes: &[bool],
+) -> Result<Vec<SuiteResult>, BenchError> {
+    let mut results = Vec::new();
+    for file in files {
+        for &weak in modes {
+            eprintln!("{file} {}", if weak { "weak" } else { "strong" });
+            results.push(SuiteResult {
+                dataset: file.clone(),
+                weak,
+                report: run(file, Arc::clone(&book), table_size, weak)?,
             });
-        let res = (
-            res.0,
-            res.1 as f64 / res.0 as f64,
-            res.2 / res.0,
-            res.1 as u128 / res.2.as_millis(),
-        );
-        println!(
-            "\ncount: {}, mean nb pos: {}, mean time: {:.2?}, K pos / s: {}",
-            res.0, res.1, res.2, res.3,
+        }
+    }
+    Ok(results)
+}
+
+pub fn format_table(results: &[SuiteResult]) -> String {
+    let mut s = format!(
+        "{:<24} {:<6} {:>6} {:>6} {:>14} {:>12} {:>10}\n",
+        "dataset", "mode", "count", "failed", "mean nb pos", "mean time", "K pos / s"
+    );
+    for r in results {
+        s += &format!(
+            "{:<24} {:<6} {:>6} {:>6} {:>14.1} {:>12} {:>10.0}\n",
+            dataset_name(&r.dataset),
+            if r.weak { "weak" } else { "strong" },
+            r.report.count(),
+            r.report.failures().count(),
+            r.report.mean_nodes(),
+            format!("{:.2?}", r.report.mean_time()),
+            r.report.kpos_per_sec(),
         );
-    } else {
-        println!("couldn't read file");
+    }
+    s
+}
+
+fn dataset_name(path: &str) -> &str {
+    std::path::Path::new(path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(path)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BaselineEntry {
+    pub dataset: String,
+    pub weak: bool,
+    pub count: usize,
+    pub failed: usize,
+    pub mean_nodes: f64,
+    pub mean_time_us: f64,
+    pub kpos_per_sec: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Baseline {
+    pub entries: Vec<BaselineEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Regression {
+    pub dataset: String,
+    pub weak: bool,
+    pub metric: &'static str,
+    pub baseline: f64,
+    pub current: f64,
+}
+impl fmt::Display for Regression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}: {} {:.1} -> {:.1}",
+            self.dataset,
+            if self.weak { "weak" } else { "strong" },
+            self.metric,
+            self.baseline,
+            self.current
+        )
+    }
+}
+
+impl Baseline {
+    pub fn from_results(results: &[SuiteResult]) -> Self {
+        let entries = results
+            .iter()
+            .map(|r| BaselineEntry {
+                dataset: dataset_name(&r.dataset).to_string(),
+                weak: r.weak,
+                count: r.report.count(),
+                failed: r.report.failures().count(),
+                mean_nodes: r.report.mean_nodes(),
+                mean_time_us: r.report.mean_time().as_secs_f64() * 1e6,
+                kpos_per_sec: r.report.kpos_per_sec(),
+            })
+            .collect();
+        Self { entries }
+    }
+    pub fn to_json(&self) -> serde_json::Value {
+        self.entries
+            .iter()
+            .map(|e| {
+                serde_json::json!({
+                    "dataset": e.dataset,
+                    "weak": e.weak,
+                    "count": e.count,
+                    "failed": e.failed,
+                    "mean_nodes": e.mean_nodes,
+                    "mean_time_us": e.mean_time_us,
+                    "kpos_per_sec": e.kpos_per_sec,
+                })
+            })
+            .collect()
+    }
+    pub fn from_json(value: &serde_json::Value) -> Option<Self> {
+        let entries = value
+            .as_array()?
+            .iter()
+            .map(|e| {
+                Some(BaselineEntry {
+                    dataset: e["dataset"].as_str()?.to_string(),
+                    weak: e["weak"].as_bool()?,
+                    count: e["count"].as_u64()? as usize,
+                    failed: e["failed"].as_u64()? as usize,
+                    mean_nodes: e["mean_nodes"].as_f64()?,
+                    mean_time_us: e["mean_time_us"].as_f64()?,
+                    kpos_per_sec: e["kpos_per_sec"].as_f64()?,
+                })
+            })
+            .collect::<Option<_>>()?;
+        Some(Self { entries })
+    }
+    pub fn save(&self, file_path: &str) -> Result<(), BenchError> {
+        let json = serde_json::to_string_pretty(&self.to_json()).map_err(IOError::from)?;
+        std::fs::write(file_path, json + "\n")?;
+        Ok(())
+    }
+    pub fn load(file_path: &str) -> Result<Self, BenchError> {
+        let s = read_file_to_string(file_path)?;
+        let error = |msg: String| BenchError::Baseline(file_path.to_string(), msg);
+        let value = serde_json::from_str(&s).map_err(|e| error(format!("invalid json, {e}")))?;
+        Self::from_json(&value).ok_or_else(|| error("not a bench baseline".to_string()))
+    }
+
+    // metrics of current worse than the baseline by more than threshold,
+    // as a fraction (0.1 == 10%), new failures are always regressions
+    pub fn compare(&self, current: &Baseline, threshold: f64) -> Vec<Regression> {
+        let mut regressions = Vec::new();
+        for cur in &current.entries {
+            let Some(base) = self
+                .entries
+                .iter()
+                .find(|b| b.dataset == cur.dataset && b.weak == cur.weak)
+            else {
+                continue;
+            };
+            let mut check = |metric, baseline: f64, current: f64, worse: bool| {
+                if worse {
+                    regressions.push(Regression {
+                        dataset: cur.dataset.clone(),
+                        weak: cur.weak,
+                        metric,
+                        baseline,
+                        current,
+                    });
+                }
+            };
+            check(
+                "failed",
+                base.failed as f64,
+                cur.failed as f64,
+                cur.failed > base.failed,
+            );
+            check(
+                "mean nodes",
+                base.mean_nodes,
+                cur.mean_nodes,
+                cur.mean_nodes > base.mean_nodes * (1.0 + threshold),
+            );
+            check(
+                "mean time us",
+                base.mean_time_us,
+                cur.mean_time_us,
+                cur.mean_time_us > base.mean_time_us * (1.0 + threshold),
+            );
+        }
+        regressions
     }
 }
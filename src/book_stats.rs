@@ -0,0 +1,93 @@
+use super::*;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PlyStats {
+    pub ply: usize,
+    pub reachable: usize,
+    pub in_book: usize,
+    // stored score -> number of book positions with that score
+    pub scores: BTreeMap<isize, usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub moves: String,
+    pub book: isize,
+    pub solver: isize,
+}
+
+// walks every position reachable in max_ply moves once, mirrored positions
+// count as one, games that are already decided are not expanded
+fn for_each_position(max_ply: usize, mut f: impl FnMut(usize, &Position, &str)) {
+    let mut level: BTreeMap<u64, (Position, String)> = BTreeMap::new();
+    level.insert(Position::new().key3(), (Position::new(), String::new()));
+    for ply in 0..=max_ply {
+        level.values().for_each(|(pos, moves)| f(ply, pos, moves));
+        if ply == max_ply {
+            break;
+        }
+        level = level
+            .values()
+            .flat_map(|(pos, moves)| {
+                (0..Position::WIDTH)
+                    .filter(|&col| pos.can_play(col) && !pos.is_winning_move(col))
+                    .map(move |col| {
+                        let next = pos.next_pos(col);
+                        (next.key3(), (next, format!("{moves}{}", col + 1)))
+                    })
+            })
+            .collect();
+    }
+}
+
+pub fn coverage(book: &OpeningBook, max_ply: usize) -> Vec<PlyStats> {
+    let mut stats: Vec<PlyStats> = (0..=max_ply)
+        .map(|ply| PlyStats {
+            ply,
+            ..Default::default()
+        })
+        .collect();
+    for_each_position(max_ply, |ply, pos, _| {
+        let s = &mut stats[ply];
+        s.reachable += 1;
+        if let Some(score) = book.score(pos) {
+            s.in_book += 1;
+            *s.scores.entry(score).or_default() += 1;
+        }
+    });
+    stats
+}
+
+// count move strings in the book at ply, spread evenly over the positions found
+pub fn sample(book: &OpeningBook, ply: usize, count: usize) -> Vec<String> {
+    let mut found = Vec::new();
+    for_each_position(ply, |p, pos, moves| {
+        if p == ply && book.get(pos).is_some() {
+            found.push(moves.to_string());
+        }
+    });
+    found.sort();
+    let step = std::cmp::max(1, found.len() / std::cmp::max(1, count));
+    found.into_iter().step_by(step).take(count).collect()
+}
+
+// solves the book positions without the book, a solver
+// sharing it would only read back the stored scores
+pub fn verify<'a>(book: &OpeningBook, lines: impl IntoIterator<Item = &'a str>) -> Vec<Mismatch> {
+    let mut solver = Solver::new();
+    lines
+        .into_iter()
+        .flat_map(|moves| {
+            let pos = Position::parse(moves);
+            let score = book.score(&pos)?;
+            solver.reset();
+            let val = solver.solve(&pos, false);
+            (val != score).then(|| Mismatch {
+                moves: moves.to_string(),
+                book: score,
+                solver: val,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,200 @@
+use super::*;
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+// one position per line, "moves score" followed by optional fields:
+//
+//   #! name: Test_L3_R1          header, key: value
+//   # anything                   comment
+//   4455 18 weak=1 analyse=-4,-3,18,2,2,18,-3 nodes=3 # trailing comment
+//
+// unplayable columns in analyse are written `-`. Plain "moves score" files
+// like the Test_L*_R* datasets are valid datasets without any extras.
+
+#[derive(Debug)]
+pub enum DatasetError {
+    Io(io::Error),
+    // 1-based line number and what is wrong with it
+    Parse(usize, String),
+}
+impl fmt::Display for DatasetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "couldn't read dataset: {e}"),
+            Self::Parse(n, msg) => write!(f, "line {n}: {msg}"),
+        }
+    }
+}
+impl Error for DatasetError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Parse(..) => None,
+        }
+    }
+}
+impl From<io::Error> for DatasetError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DatasetEntry {
+    pub line: usize,
+    pub moves: String,
+    pub position: Position,
+    pub score: isize,
+    pub weak: Option<isize>,
+    pub analyse: Option<Vec<Option<isize>>>,
+    pub nodes: Option<u64>,
+    pub comment: Option<String>,
+}
+impl DatasetEntry {
+    pub fn new(moves: &str, score: isize) -> Option<Self> {
+        Some(Self {
+            line: 0,
+            moves: moves.to_string(),
+            position: Position::try_parse(moves)?,
+            score,
+            weak: None,
+            analyse: None,
+            nodes: None,
+            comment: None,
+        })
+    }
+    // weak score given in the file, or the sign of the exact score
+    pub fn weak_score(&self) -> isize {
+        self.weak.unwrap_or(self.score.signum())
+    }
+
+    fn parse(n: usize, line: &str) -> Result<Self, DatasetError> {
+        let err = |msg: String| DatasetError::Parse(n, msg);
+        let (fields, comment) = match line.split_once('#') {
+            Some((fields, comment)) => (fields, Some(comment.trim().to_string())),
+            None => (line, None),
+        };
+        let mut fields = fields.split_whitespace();
+        let moves = fields
+            .next()
+            .ok_or_else(|| err("missing moves".to_string()))?;
+        let score = fields
+            .next()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| err(format!("missing score after {moves:?}")))?;
+        let mut entry =
+            Self::new(moves, score).ok_or_else(|| err(format!("invalid position {moves:?}")))?;
+        entry.line = n;
+        entry.comment = comment;
+        for field in fields {
+            let invalid = || err(format!("invalid field {field:?}"));
+            match field.split_once('=').ok_or_else(invalid)? {
+                ("weak", v) => entry.weak = Some(v.parse().map_err(|_| invalid())?),
+                ("nodes", v) => entry.nodes = Some(v.parse().map_err(|_| invalid())?),
+                ("analyse", v) => {
+                    let scores = v
+                        .split(',')
+                        .map(|s| match s {
+                            "-" => Ok(None),
+                            s => s.parse().map(Some),
+                        })
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|_| invalid())?;
+                    if scores.len() != Position::WIDTH {
+                        return Err(invalid());
+                    }
+                    entry.analyse = Some(scores);
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(entry)
+    }
+}
+impl fmt::Display for DatasetEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.moves, self.score)?;
+        if let Some(weak) = self.weak {
+            write!(f, " weak={weak}")?;
+        }
+        if let Some(scores) = &self.analyse {
+            let scores: Vec<_> = scores
+                .iter()
+                .map(|s| s.map_or("-".to_string(), |s| s.to_string()))
+                .collect();
+            write!(f, " analyse={}", scores.join(","))?;
+        }
+        if let Some(nodes) = self.nodes {
+            write!(f, " nodes={nodes}")?;
+        }
+        if let Some(comment) = &self.comment {
+            write!(f, " # {comment}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Dataset {
+    pub headers: Vec<(String, String)>,
+    pub entries: Vec<DatasetEntry>,
+}
+impl Dataset {
+    pub fn parse(s: &str) -> Result<Self, DatasetError> {
+        let (dataset, mut errors) = Self::parse_lenient(s);
+        match errors.is_empty() {
+            true => Ok(dataset),
+            false => Err(errors.swap_remove(0)),
+        }
+    }
+    // the valid lines and the errors of the others, in the order of the lines
+    pub fn parse_lenient(s: &str) -> (Self, Vec<DatasetError>) {
+        let mut dataset = Self::default();
+        let mut errors = Vec::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if let Some(header) = line.strip_prefix("#!") {
+                match header.split_once(':') {
+                    Some((key, value)) => dataset
+                        .headers
+                        .push((key.trim().to_string(), value.trim().to_string())),
+                    None => errors.push(DatasetError::Parse(
+                        i + 1,
+                        format!("invalid header {line:?}"),
+                    )),
+                }
+            } else if !line.is_empty() && !line.starts_with('#') {
+                match DatasetEntry::parse(i + 1, line) {
+                    Ok(entry) => dataset.entries.push(entry),
+                    Err(e) => errors.push(e),
+                }
+            }
+        }
+        (dataset, errors)
+    }
+    pub fn load(file_path: &str) -> Result<Self, DatasetError> {
+        Self::parse(&std::fs::read_to_string(file_path)?)
+    }
+    pub fn save(&self, file_path: &str) -> Result<(), DatasetError> {
+        std::fs::write(file_path, self.to_string())?;
+        Ok(())
+    }
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+}
+impl fmt::Display for Dataset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (key, value) in &self.headers {
+            writeln!(f, "#! {key}: {value}")?;
+        }
+        for entry in &self.entries {
+            writeln!(f, "{entry}")?;
+        }
+        Ok(())
+    }
+}
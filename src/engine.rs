@@ -0,0 +1,348 @@
+use super::*;
+use position::{Notation, OpeningBook};
+use solver::Solver;
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// uci-like protocol for gui and arena tools, moves are columns 1-7:
+//
+//   > uci                          < id name connect_4_ai
+//                                  < option name Hash type spin default 40 min 1 max 4096
+//                                  < option name Weak type check default false
+//                                  < uciok
+//   > position startpos moves 4 4 5
+//   > go movetime 1000             < info depth 1 score cp -9 nodes 56 time 0 pv 3
+//                                  < ...
+//                                  < bestmove 3
+//   > position moves 52753311433677442422121
+//   > go                           < info depth 1 score cp 10 nodes 38 time 0 pv 4
+//                                  < ...
+//                                  < info depth 19 score mate 3 nodes 83836 time 74 pv 5 5 3 4 6
+//                                  < bestmove 5
+//
+// go takes movetime <ms>, depth <plies> or infinite (the default), it
+// searches deeper and deeper with Position::evaluate and then solves the
+// position unless depth is given. stop and quit end the search with the
+// best move found so far, as does the end of the input unless a movetime
+// or depth limits the search, then it waits for it.
+
+// plies of heuristic search before solving
+const HEURISTIC_DEPTH: usize = 12;
+
+// transposition table entries in a table of mb megabytes
+fn table_size(mb: usize) -> usize {
+    (mb << 20) / 5
+}
+
+// how long and how deep go searches
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Limits {
+    movetime: Option<Duration>,
+    depth: Option<usize>,
+}
+impl Limits {
+    fn parse(args: &[&str]) -> Result<Self, String> {
+        let mut limits = Self::default();
+        let mut args = args.iter();
+        while let Some(&arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .and_then(|v| v.parse::<u64>().ok())
+                    .ok_or_else(|| format!("go {arg} needs a number"))
+            };
+            match arg {
+                "movetime" => limits.movetime = Some(Duration::from_millis(value()?)),
+                "depth" => limits.depth = Some(value()? as usize),
+                "infinite" => {}
+                _ => return Err(format!("unknown go option {arg:?}")),
+            }
+        }
+        Ok(limits)
+    }
+}
+
+// score of the side to move as uci reports it, mate in moves of the
+// winner for exact scores and cp otherwise
+fn uci_score(pos: &Position, score: isize, exact: bool) -> String {
+    let stones = (Position::WIDTH * Position::HEIGHT + 1) as isize;
+    let moves = pos.moves as isize;
+    match (exact, score) {
+        (false, s) | (true, s @ 0) => format!("cp {s}"),
+        (true, s) if s > 0 => format!("mate {}", (stones - 2 * s - moves) / 2 + 1),
+        (true, s) => format!("mate -{}", (stones + 2 * s - moves - 1) / 2 + 1),
+    }
+}
+
+struct Search {
+    pos: Position,
+    limits: Limits,
+    weak: bool,
+    start: Instant,
+    // node count of the solver when the search started
+    nodes: u64,
+}
+impl Search {
+    fn info(
+        &self,
+        out: &Mutex<impl Write>,
+        solver: &Solver,
+        depth: usize,
+        score: String,
+        pv: &[usize],
+    ) {
+        let pv: Vec<_> = pv.iter().map(|col| (col + 1).to_string()).collect();
+        let line = format!(
+            "info depth {depth} score {score} nodes {} time {} pv {}",
+            solver.node_count - self.nodes,
+            self.start.elapsed().as_millis(),
+            pv.join(" ")
+        );
+        // a closed output only loses the report
+        let mut out = out.lock().unwrap();
+        let _ = writeln!(out, "{}", line.trim_end()).and_then(|_| out.flush());
+    }
+
+    // best column found before the limits or a stop ran out
+    fn run(&self, solver: &mut Solver, out: &Mutex<impl Write>) -> Option<usize> {
+        // centre first until a search finishes
+        let mut best = solver::best_move(
+            &(0..Position::WIDTH)
+                .map(|col| self.pos.can_play(col).then_some(0))
+                .collect::<Vec<_>>(),
+        );
+        if let Some(col) = (0..Position::WIDTH).find(|&col| self.pos.is_winning_move(col)) {
+            self.info(out, solver, 1, "mate 1".to_string(), &[col]);
+            return Some(col);
+        }
+        let max_depth = self.limits.depth.unwrap_or(HEURISTIC_DEPTH);
+        for depth in 1..=max_depth {
+            let scores = solver.analyse_depth(&self.pos, depth);
+            if solver.stopped() {
+                return best;
+            }
+            best = solver::best_move(&scores);
+            let col = best?;
+            let best_score = scores[col].expect("a playable column");
+            let score = match best_score.score() {
+                Some(s) => uci_score(&self.pos, s, true),
+                None => uci_score(&self.pos, best_score.value, false),
+            };
+            self.info(out, solver, depth, score, &[col]);
+            // the rest of the game is searched already
+            if scores.iter().flatten().all(|s| s.exact) {
+                break;
+            }
+        }
+        if self.limits.depth.is_some() {
+            return best;
+        }
+        let analysis = solver.analysis(&self.pos, self.weak);
+        if solver.stopped() {
+            return best;
+        }
+        let pv = solver.principal_variation(&self.pos, self.weak);
+        if solver.stopped() || analysis.best_move.is_none() {
+            return analysis.best_move.or(best);
+        }
+        let depth = Position::WIDTH * Position::HEIGHT - self.pos.moves;
+        let exact = uci_score(&self.pos, analysis.score.expect("a best move"), !self.weak);
+        self.info(out, solver, depth, exact, &pv);
+        analysis.best_move
+    }
+}
+
+pub struct Engine<W: Write + Send + 'static> {
+    book: Arc<OpeningBook>,
+    // None while a search owns it
+    solver: Option<Solver>,
+    stop: Arc<AtomicBool>,
+    search: Option<JoinHandle<Solver>>,
+    // limits of the last go
+    limits: Limits,
+    out: Arc<Mutex<W>>,
+    // table size in megabytes, the Hash option
+    hash: usize,
+    weak: bool,
+    pos: Position,
+}
+
+impl<W: Write + Send + 'static> Engine<W> {
+    pub fn new(book: Arc<OpeningBook>, table_size: usize, out: W) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut solver = Solver::with_table_size(Arc::clone(&book), table_size);
+        solver.set_stop_flag(Arc::clone(&stop));
+        Self {
+            book,
+            solver: Some(solver),
+            stop,
+            search: None,
+            limits: Limits::default(),
+            out: Arc::new(Mutex::new(out)),
+            hash: ((table_size * 5) >> 20).max(1),
+            weak: false,
+            pos: Position::new(),
+        }
+    }
+
+    fn send(&self, line: &str) -> io::Result<()> {
+        let mut out = self.out.lock().unwrap();
+        writeln!(out, "{line}")?;
+        out.flush()
+    }
+
+    // waits for the running search, stopping it first if asked to
+    fn wait(&mut self, stop: bool) {
+        let Some(search) = self.search.take() else {
+            return;
+        };
+        if stop {
+            self.stop.store(true, Ordering::Relaxed);
+        }
+        self.solver = Some(search.join().expect("search thread"));
+        self.stop.store(false, Ordering::Relaxed);
+    }
+
+    fn solver(&mut self) -> &mut Solver {
+        self.wait(true);
+        self.solver.as_mut().expect("no running search")
+    }
+
+    // handles one command, false after quit
+    pub fn command(&mut self, line: &str) -> io::Result<bool> {
+        let words: Vec<_> = line.split_whitespace().collect();
+        let Some((&command, args)) = words.split_first() else {
+            return Ok(true);
+        };
+        match command {
+            "uci" => {
+                self.send("id name connect_4_ai")?;
+                let hash = self.hash;
+                self.send(&format!(
+                    "option name Hash type spin default {hash} min 1 max 4096"
+                ))?;
+                self.send("option name Weak type check default false")?;
+                self.send("uciok")?;
+            }
+            "isready" => self.send("readyok")?,
+            "ucinewgame" => self.solver().reset(),
+            "setoption" => self.set_option(args)?,
+            "position" => match Self::position(args) {
+                Ok(pos) => self.pos = pos,
+                Err(e) => self.send(&format!("info string {e}"))?,
+            },
+            "go" => match Limits::parse(args) {
+                Ok(limits) => self.go(limits),
+                Err(e) => self.send(&format!("info string {e}"))?,
+            },
+            "stop" => self.wait(true),
+            "quit" => {
+                self.wait(true);
+                return Ok(false);
+            }
+            _ => self.send(&format!("info string unknown command {command:?}"))?,
+        }
+        Ok(true)
+    }
+
+    // "name <id> value <x>", names are case insensitive as in uci
+    fn set_option(&mut self, args: &[&str]) -> io::Result<()> {
+        let (name, value) = match args {
+            ["name", name, "value", value] => (name.to_ascii_lowercase(), *value),
+            _ => {
+                return self.send(&format!(
+                    "info string invalid setoption {:?}",
+                    args.join(" ")
+                ))
+            }
+        };
+        match (name.as_str(), value.parse::<usize>(), value) {
+            ("hash", Ok(mb), _) if (1..=4096).contains(&mb) => {
+                self.wait(true);
+                self.hash = mb;
+                let mut solver = Solver::with_table_size(Arc::clone(&self.book), table_size(mb));
+                solver.set_stop_flag(Arc::clone(&self.stop));
+                self.solver = Some(solver);
+            }
+            ("weak", _, "true") => self.weak = true,
+            ("weak", _, "false") => self.weak = false,
+            _ => return self.send(&format!("info string invalid {name} value {value:?}")),
+        }
+        Ok(())
+    }
+
+    // "startpos [moves ...]" or "moves ...", moves can be run together
+    fn position(args: &[&str]) -> Result<Position, String> {
+        let args = args.strip_prefix(&["startpos"]).unwrap_or(args);
+        let moves = match args {
+            [] => String::new(),
+            ["moves", moves @ ..] => moves.concat(),
+            _ => return Err(format!("invalid position {:?}", args.join(" "))),
+        };
+        Position::parse_notation(&moves, Notation::OneBased)
+            .ok_or_else(|| format!("invalid moves {moves:?}"))
+    }
+
+    fn go(&mut self, limits: Limits) {
+        self.wait(true);
+        self.limits = limits;
+        let mut solver = self.solver.take().expect("no running search");
+        let search = Search {
+            pos: self.pos.clone(),
+            limits,
+            weak: self.weak,
+            start: Instant::now(),
+            nodes: solver.node_count,
+        };
+        let (stop, out) = (Arc::clone(&self.stop), Arc::clone(&self.out));
+        self.search = Some(thread::spawn(move || {
+            let (done, wait) = mpsc::channel::<()>();
+            let timer = limits.movetime.map(|movetime| {
+                thread::spawn(move || {
+                    if wait.recv_timeout(movetime) == Err(RecvTimeoutError::Timeout) {
+                        stop.store(true, Ordering::Relaxed);
+                    }
+                })
+            });
+            let best = search.run(&mut solver, &out);
+            drop(done);
+            if let Some(timer) = timer {
+                timer.join().expect("timer thread");
+            }
+            let line = match best {
+                Some(col) => format!("bestmove {}", col + 1),
+                None => "bestmove none".to_string(),
+            };
+            let mut out = out.lock().unwrap();
+            let _ = writeln!(out, "{line}").and_then(|_| out.flush());
+            solver
+        }));
+    }
+
+    // waits for the running search and hands back the output
+    pub fn finish(mut self) -> W {
+        // a search without limits would never end on its own
+        self.wait(self.limits == Limits::default());
+        let out = Arc::try_unwrap(self.out).ok().expect("search finished");
+        out.into_inner().unwrap()
+    }
+}
+
+// answers the commands from input until quit or the end of the input
+pub fn run<W: Write + Send + 'static>(
+    book: Arc<OpeningBook>,
+    table_size: usize,
+    input: impl BufRead,
+    output: W,
+) -> io::Result<W> {
+    let mut engine = Engine::new(book, table_size, output);
+    for line in input.lines() {
+        if !engine.command(&line?)? {
+            break;
+        }
+    }
+    Ok(engine.finish())
+}
@@ -0,0 +1,205 @@
+use super::*;
+use player::AiPlayer;
+use solver::Solver;
+use std::io::{self, BufRead, Write};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Ongoing,
+    // player 1 or 2
+    Won(usize),
+    Draw,
+}
+
+// a game as a list of columns, unlike Position it knows when it is over
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Game {
+    history: Vec<usize>,
+    positions: Vec<Position>,
+    status: Option<Status>,
+}
+
+impl Game {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    // replays 1-based moves, None if one is illegal
+    pub fn from_moves(moves: &str) -> Option<Self> {
+        let mut game = Self::new();
+        for c in moves.chars() {
+            let col = c.to_digit(10).filter(|d| *d > 0)? as usize - 1;
+            if !game.play(col) {
+                return None;
+            }
+        }
+        Some(game)
+    }
+    // replays some move order leading to pos, see Position::move_order
+    pub fn from_position(pos: &Position) -> Option<Self> {
+        let mut game = Self::new();
+        for col in pos.move_order()? {
+            game.play(col);
+        }
+        Some(game)
+    }
+    pub fn position(&self) -> Position {
+        self.positions.last().cloned().unwrap_or_default()
+    }
+    pub fn history(&self) -> &[usize] {
+        &self.history
+    }
+    // the moves in the 1-based notation of Position::parse
+    pub fn moves(&self) -> String {
+        self.history.iter().map(|c| (c + 1).to_string()).collect()
+    }
+    pub fn status(&self) -> Status {
+        self.status.unwrap_or(Status::Ongoing)
+    }
+    pub fn can_play(&self, col: usize) -> bool {
+        self.status() == Status::Ongoing && col < Position::WIDTH && self.position().can_play(col)
+    }
+    pub fn play(&mut self, col: usize) -> bool {
+        if !self.can_play(col) {
+            return false;
+        }
+        let pos = self.position();
+        let next = pos.next_pos(col);
+        if pos.is_winning_move(col) {
+            self.status = Some(Status::Won(pos.current_player()));
+        } else if next.moves == Position::WIDTH * Position::HEIGHT {
+            self.status = Some(Status::Draw);
+        }
+        self.history.push(col);
+        self.positions.push(next);
+        true
+    }
+    pub fn undo(&mut self) -> Option<usize> {
+        self.positions.pop();
+        self.status = None;
+        self.history.pop()
+    }
+}
+
+// lets ai move in game, an error if it finds no move or an illegal one
+fn play_move(game: &mut Game, ai: &mut dyn AiPlayer) -> io::Result<usize> {
+    let pos = game.position();
+    let col = ai.choose(&pos);
+    match col {
+        Some(col) if game.play(col) => Ok(col),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} chose {} after {:?}",
+                ai.name(),
+                col.map_or("no move".to_string(), |col| format!("column {}", col + 1)),
+                game.moves()
+            ),
+        )),
+    }
+}
+
+// plays game to the end, players[0] moves for player 1
+pub fn play_ai(mut game: Game, players: [&mut dyn AiPlayer; 2]) -> io::Result<Game> {
+    let [first, second] = players;
+    while game.status() == Status::Ongoing {
+        let ai = if game.position().current_player() == 1 {
+            &mut *first
+        } else {
+            &mut *second
+        };
+        play_move(&mut game, ai)?;
+    }
+    Ok(game)
+}
+
+// the board as Position prints it, but wide enough to show the score of
+// every playable column right above it
+fn board(pos: &Position, hints: Option<&[Option<isize>]>) -> String {
+    let row = |cells: Vec<String>| {
+        let cells: Vec<_> = cells.iter().map(|c| format!("{c:>3}")).collect();
+        cells.join(" ")
+    };
+    let mut lines = vec![format!("player: {}", pos.current_player())];
+    if let Some(hints) = hints {
+        let hints = hints
+            .iter()
+            .map(|s| s.map_or("-".to_string(), |s| s.to_string()));
+        lines.push(row(hints.collect()));
+    }
+    for r in (0..Position::HEIGHT).rev() {
+        let cells = (0..Position::WIDTH).map(|col| match pos.cell(col, r) {
+            Some(1) => "X".to_string(),
+            Some(_) => "O".to_string(),
+            None => ".".to_string(),
+        });
+        lines.push(row(cells.collect()));
+    }
+    lines.push(row((1..=Position::WIDTH).map(|c| c.to_string()).collect()));
+    lines.join("\n")
+}
+
+// human against ai on a terminal from game, human is player 1 or 2,
+// hints come from the solver whatever the strength of ai
+pub fn play_terminal(
+    solver: &mut Solver,
+    ai: &mut dyn AiPlayer,
+    mut game: Game,
+    human: usize,
+    mut input: impl BufRead,
+    mut output: impl Write,
+) -> io::Result<Status> {
+    let start = game.history().len();
+    let side = if human == 1 { "X" } else { "O" };
+    writeln!(output, "you play {side} against {}", ai.name())?;
+    // solver scores shown above the board until the next command
+    let mut hints = None;
+    loop {
+        let pos = game.position();
+        writeln!(output, "\n{}", board(&pos, hints.take().as_deref()))?;
+        match game.status() {
+            Status::Won(p) if p == human => writeln!(output, "you win!")?,
+            Status::Won(_) => writeln!(output, "the ai wins")?,
+            Status::Draw => writeln!(output, "draw")?,
+            Status::Ongoing if pos.current_player() != human => {
+                let col = play_move(&mut game, ai)?;
+                writeln!(output, "ai plays {}", col + 1)?;
+                continue;
+            }
+            Status::Ongoing => {}
+        }
+        if game.status() == Status::Ongoing {
+            write!(output, "your move (1-7, h = hint, u = undo, q = quit): ")?;
+        } else {
+            write!(output, "u = undo, q = quit: ")?;
+        }
+        output.flush()?;
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(game.status());
+        }
+        match line.trim() {
+            "q" => return Ok(game.status()),
+            "h" => hints = Some(solver.analyse(&pos, false)),
+            "u" => {
+                // back to the last position where it was the human's turn,
+                // never before the position the game started from
+                let mut undone = false;
+                while game.history().len() > start
+                    && (!undone || game.position().current_player() != human)
+                {
+                    game.undo();
+                    undone = true;
+                }
+                if game.position().current_player() != human || !undone {
+                    writeln!(output, "nothing to undo")?;
+                }
+            }
+            s => match s.parse::<usize>() {
+                Ok(col) if col >= 1 && game.can_play(col - 1) => {
+                    game.play(col - 1);
+                }
+                _ => writeln!(output, "invalid move {s:?}")?,
+            },
+        }
+    }
+}
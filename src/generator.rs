@@ -0,0 +1,128 @@
+use super::*;
+use rng::Rng;
+use solver::Solver;
+use std::collections::HashSet;
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+// game stage of the Test_L* datasets, by number of moves played
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    Begin,
+    Middle,
+    End,
+}
+impl Stage {
+    pub fn plies(self) -> RangeInclusive<usize> {
+        match self {
+            Self::Begin => 0..=13,
+            Self::Middle => 14..=27,
+            Self::End => 28..=Position::WIDTH * Position::HEIGHT - 1,
+        }
+    }
+}
+
+// difficulty of the *_R* datasets, by nodes the solver explores
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+impl Difficulty {
+    pub fn nodes(self) -> RangeInclusive<u64> {
+        match self {
+            Self::Easy => 0..=99_999,
+            Self::Medium => 100_000..=9_999_999,
+            Self::Hard => 10_000_000..=u64::MAX,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Generated {
+    pub moves: String,
+    pub score: isize,
+    pub nodes: u64,
+}
+
+pub struct Generator {
+    pub plies: RangeInclusive<usize>,
+    pub nodes: RangeInclusive<u64>,
+    rng: Rng,
+    seen: HashSet<u64>,
+}
+
+impl Generator {
+    pub fn new(plies: RangeInclusive<usize>, nodes: RangeInclusive<u64>, seed: u64) -> Self {
+        assert!(*plies.end() < Position::WIDTH * Position::HEIGHT);
+        Self {
+            plies,
+            nodes,
+            rng: Rng::new(seed),
+            seen: HashSet::new(),
+        }
+    }
+    pub fn with_stage(stage: Stage, difficulty: Difficulty, seed: u64) -> Self {
+        Self::new(stage.plies(), difficulty.nodes(), seed)
+    }
+
+    // random game that is still undecided after a random number of plies in range
+    fn random_game(&mut self) -> Option<(String, Position)> {
+        let (lo, hi) = (*self.plies.start(), *self.plies.end());
+        let ply = lo + self.rng.below(hi - lo + 1);
+        let mut pos = Position::new();
+        let mut moves = String::new();
+        while pos.moves < ply {
+            let cols: Vec<_> = (0..Position::WIDTH)
+                .filter(|&col| pos.can_play(col) && !pos.is_winning_move(col))
+                .collect();
+            let col = self.rng.choose(&cols)?;
+            moves.push_str(&(col + 1).to_string());
+            pos = pos.next_pos(col);
+        }
+        Some((moves, pos))
+    }
+
+    // next position that is new up to symmetry and whose solve
+    // falls in the node range, tries at most `attempts` games
+    pub fn next(&mut self, solver: &mut Solver, attempts: usize) -> Option<Generated> {
+        for _ in 0..attempts {
+            let Some((moves, pos)) = self.random_game() else {
+                continue;
+            };
+            if !self.seen.insert(pos.symmetric_key()) {
+                continue;
+            }
+            solver.reset();
+            let score = solver.solve(&pos, false);
+            if self.nodes.contains(&solver.node_count) {
+                return Some(Generated {
+                    moves,
+                    score,
+                    nodes: solver.node_count,
+                });
+            }
+        }
+        None
+    }
+
+    // writes up to count "moves score nodes=n" dataset lines
+    pub fn write(
+        &mut self,
+        solver: &mut Solver,
+        count: usize,
+        attempts: usize,
+        mut out: impl Write,
+    ) -> io::Result<usize> {
+        let mut written = 0;
+        while written < count {
+            let Some(g) = self.next(solver, attempts) else {
+                break;
+            };
+            writeln!(out, "{} {} nodes={}", g.moves, g.score, g.nodes)?;
+            written += 1;
+        }
+        Ok(written)
+    }
+}
@@ -1,17 +1,42 @@
-pub use position::{OpeningBook, Position};
+pub use position::{BookError, OpeningBook, Position};
 pub use solver::Solver;
 pub use transposition_table::TranspositionTable;
 
+pub mod batch;
 pub mod bencher;
+pub mod book_stats;
+pub mod dataset;
+pub mod engine;
+pub mod game;
+pub mod generator;
+pub mod mcts;
+pub mod player;
 pub mod position;
+pub mod record;
+pub mod rng;
+#[cfg(feature = "server")]
+pub mod server;
 pub mod solver;
+pub mod tournament;
 pub mod transposition_table;
+#[cfg(feature = "tui")]
+pub mod tui;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use position::OpeningBook;
+    use position::{BookError, BookHeader, OpeningBook};
     use solver::Solver;
+    use transposition_table::OptimizedTranspoisitionTable;
+
+    // unique per run, tests of other checkouts may run at the same time
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        let nanos = std::time::UNIX_EPOCH.elapsed().unwrap().as_nanos();
+        let name = format!("connect_4_ai_{name}_{}_{nanos}", std::process::id());
+        std::env::temp_dir().join(name)
+    }
 
     #[test]
     fn test_parsing() {
@@ -31,18 +56,284 @@ mod tests {
         assert!(pos.is_some());
         let pos = Position::parse_safe("01234567");
         assert!(pos.is_none());
+        assert!(Position::parse_safe("9").is_none());
+    }
+    #[test]
+    fn test_notations() {
+        use position::Notation;
+        let pos = Position::parse("4453");
+        let codes = [
+            (Notation::OneBased, "4453"),
+            (Notation::ZeroBased, "3342"),
+            (Notation::Letters, "ddec"),
+            (Notation::Reference, "4453"),
+        ];
+        for (notation, code) in codes {
+            assert_eq!(
+                Some(&pos),
+                Position::parse_notation(code, notation).as_ref()
+            );
+        }
+        assert_eq!(None, Position::parse_notation("DDEC", Notation::Letters));
+        assert_eq!(
+            Some(pos.clone()),
+            Position::parse_notation("4453 -2", Notation::Reference)
+        );
+        assert_eq!(
+            None,
+            Position::parse_notation("4453 x", Notation::Reference)
+        );
+        assert_eq!(None, Position::parse_notation("4457", Notation::ZeroBased));
+        assert_eq!(None, Position::parse_notation("ddeh", Notation::Letters));
+        // the game is over after the seventh move
+        assert_eq!(
+            None,
+            Position::parse_notation("12121212", Notation::OneBased)
+        );
+
+        // formatting finds some move order, not necessarily the one played
+        for moves in [
+            "",
+            "4453",
+            "52753311433677442422121",
+            "2737772244262123677516643354",
+        ] {
+            let pos = Position::parse(moves);
+            for notation in Notation::ALL {
+                let code = pos.format(notation).unwrap();
+                assert_eq!(
+                    Some(&pos),
+                    Position::parse_notation(&code, notation).as_ref()
+                );
+            }
+        }
+        let key = pos.format(Notation::Key).unwrap();
+        assert_eq!(9, key.len());
+        assert_eq!(None, Position::parse_notation("_________", Notation::Key));
+        // player 2 under player 1 in the first column, no game gets there
+        assert_eq!(None, Position::parse_notation("AAAAAAAAF", Notation::Key));
+        // the four is fine as the last move, not with a move after it
+        assert_eq!(
+            Some(vec![0, 1, 0, 1, 0, 1, 0]),
+            Position::parse("1212121").move_order()
+        );
+        assert_eq!(None, Position::parse("12121212").move_order());
+        // but finished games are neither formatted nor parsed
+        for notation in Notation::ALL {
+            assert_eq!(None, Position::parse("1212121").format(notation));
+        }
+        let finished = Position::parse("1212121").key64();
+        assert_eq!(None, Position::parse_notation(&finished, Notation::Key));
+        assert_eq!(
+            None,
+            Position::parse_notation("1212121", Notation::OneBased)
+        );
+
+        assert_eq!(Some(pos.clone()), Position::parse_any("ddec"));
+        assert_eq!(Some(pos), Position::parse_any(&key));
+        assert_eq!(Some(Position::parse("77")), Position::parse_any("77"));
+        // 1-based and 0-based disagree
+        assert_eq!(None, Position::parse_any("4453"));
+    }
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let pos = Position::parse("52753311433677442422121");
+        let json = serde_json::to_string(&pos).unwrap();
+        assert_eq!(format!("{:?}", pos.key64()), json);
+        assert_eq!(pos, serde_json::from_str(&json).unwrap());
+        assert!(serde_json::from_str::<Position>("\"4455\"").is_err());
+        // a finished game would not read back
+        let finished = Position::parse("1212121");
+        assert!(serde_json::to_string(&finished).is_err());
+        let json = format!("{:?}", finished.key64());
+        assert!(serde_json::from_str::<Position>(&json).is_err());
+
+        let analysis = Solver::new().analysis(&pos, false);
+        assert_eq!((Some(4), Some(8)), (analysis.best_move, analysis.score));
+        let json = serde_json::to_value(&analysis).unwrap();
+        assert_eq!(8, json["score"]);
+        assert_eq!(json["position"], serde_json::to_value(&pos).unwrap());
+        let back: solver::Analysis = serde_json::from_value(json).unwrap();
+        assert_eq!(analysis, back);
+
+        let report = bencher::BenchReport {
+            samples: vec![bencher::BenchSample {
+                moves: "4455".to_string(),
+                expected: 18,
+                score: 18,
+                nodes: 3,
+                time: std::time::Duration::from_micros(5),
+                passed: true,
+            }],
+            skipped: vec![(2, "invalid position \"4x\"".to_string())],
+        };
+        let json = serde_json::to_string(&report).unwrap();
+        assert_eq!(report, serde_json::from_str(&json).unwrap());
+    }
+    #[cfg(feature = "server")]
+    #[test]
+    fn test_server() {
+        use server::{Api, Config, Worker};
+        let config = Config {
+            timeout: std::time::Duration::from_millis(200),
+            cache_size: 2,
+            ..Default::default()
+        };
+        let api = Api::new(&config);
+        let mut worker = Worker::new(Default::default(), config.table_size);
+        let (status, body) = api.handle(&mut worker, "GET", "/solve?pos=52753311433677442422121");
+        assert_eq!((200, 8), (status, body["score"].as_i64().unwrap()));
+        assert!(body["nodes"].as_u64().unwrap() > 0);
+        // same position in another notation comes from the cache
+        let (status, body) = api.handle(
+            &mut worker,
+            "GET",
+            "/solve?pos=41642200322566331311010&notation=zero",
+        );
+        assert_eq!((200, 8), (status, body["score"].as_i64().unwrap()));
+        assert_eq!("41642200322566331311010", body["pos"]);
+
+        let (_, body) = api.handle(&mut worker, "GET", "/bestmove?pos=52753311433677442422121");
+        assert_eq!(
+            (5, 8),
+            (
+                body["move"].as_i64().unwrap(),
+                body["score"].as_i64().unwrap()
+            )
+        );
+        let (_, body) = api.handle(
+            &mut worker,
+            "GET",
+            "/solve?pos=52753311433677442422121&weak",
+        );
+        // weak scores only tell the sign
+        assert!(body["score"].as_i64().unwrap() > 0);
+        assert_eq!(true, body["weak"]);
+        let (_, body) = api.handle(
+            &mut worker,
+            "GET",
+            "/analyse?pos=2737772244262123677516643354",
+        );
+        assert_eq!(7, body["scores"].as_array().unwrap().len());
+
+        // query names and values are percent-decoded
+        let (status, body) = api.handle(&mut worker, "GET", "/solve?%70os=%34455&weak=tru%65");
+        assert_eq!(
+            (200, "4455", true),
+            (status, body["pos"].as_str().unwrap(), body["weak"] == true)
+        );
+        for bad in ["/solve?pos=44%5", "/solve?pos=44%+5", "/solve?pos=%ff"] {
+            assert_eq!(400, api.handle(&mut worker, "GET", bad).0);
+        }
+        assert_eq!(400, api.handle(&mut worker, "GET", "/solve?pos=4x").0);
+        assert_eq!(
+            400,
+            api.handle(&mut worker, "GET", "/solve?pos=44&weak=maybe").0
+        );
+        assert_eq!(404, api.handle(&mut worker, "GET", "/play?pos=44").0);
+        assert_eq!(405, api.handle(&mut worker, "POST", "/solve").0);
+        // far too slow to solve in 200ms without a book
+        let (status, body) = api.handle(&mut worker, "GET", "/solve?pos=");
+        assert_eq!(503, status);
+        assert!(body["error"].as_str().unwrap().contains("200 ms"));
+        // the worker is usable again afterwards
+        let (status, _) = api.handle(
+            &mut worker,
+            "GET",
+            "/solve?pos=2737772244262123677516643354",
+        );
+        assert_eq!(200, status);
+    }
+    #[cfg(feature = "wasm")]
+    #[test]
+    fn test_wasm() {
+        let mut solver = wasm::WasmSolver::new(Some(wasm::DEFAULT_TABLE_SIZE));
+        assert_eq!(Ok(8), solver.try_solve("41642200322566331311010", false));
+        assert_eq!(
+            Ok(Some(4)),
+            solver.try_best_move("41642200322566331311010", false)
+        );
+        let scores = solver.try_analyse("1626661133151012566405532243", false);
+        assert_eq!(Some(0), scores.unwrap().into_iter().flatten().max());
+        assert!(solver.try_solve("337", false).is_err());
+        // columns past the board and moves after a four
+        assert!(solver.try_solve("9", false).is_err());
+        assert!(solver.try_solve("0101010", false).is_err());
+        assert!(solver.try_solve("01010101", false).is_err());
+        assert!(solver.nodes() > 0.0);
+    }
+    #[test]
+    fn test_engine() {
+        let run = |input: &str| {
+            let out = engine::run(
+                Default::default(),
+                OptimizedTranspoisitionTable::MIN_SIZE,
+                input.as_bytes(),
+                Vec::new(),
+            );
+            String::from_utf8(out.unwrap()).unwrap()
+        };
+        let out = run("uci\nsetoption name Hash value 1\nisready\nposition moves 1 2 8\n");
+        assert!(out.contains("uciok\nreadyok\ninfo string invalid moves"));
+
+        // the end of the input waits for a limited search to finish
+        let out = run("position startpos moves 5275331143 3677442422121\ngo movetime 60000\n");
+        let last: Vec<_> = out.lines().rev().take(2).collect();
+        assert_eq!("bestmove 5", last[0]);
+        assert!(last[1].starts_with("info depth 19 score mate 3 nodes"));
+        assert!(last[1].ends_with("pv 5 5 3 4 6"));
+        let out = run("position moves 2737772244262123677516643354\ngo depth 1\n");
+        assert!(out.starts_with("info depth 1 score cp "));
+        assert_eq!(2, out.lines().count());
+
+        // far too slow to solve, stopped by the movetime and by stop
+        let out = run("position startpos\ngo movetime 50\n");
+        assert!(out.lines().last().unwrap().starts_with("bestmove "));
+        let out = run("go infinite\nstop\nisready\n");
+        assert!(out.ends_with("\nreadyok\n") && out.contains("bestmove "));
+        // and stops an unlimited one
+        for go in ["go", "go infinite"] {
+            let out = run(&format!("position startpos\n{go}\n"));
+            assert!(out.lines().last().unwrap().starts_with("bestmove "));
+        }
+
+        // every line reaches a buffered output as it is written
+        #[derive(Default)]
+        struct Buffered {
+            pending: Vec<u8>,
+            flushed: Vec<String>,
+        }
+        impl std::io::Write for Buffered {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.pending.write(buf)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                let line = String::from_utf8(std::mem::take(&mut self.pending));
+                self.flushed.push(line.unwrap());
+                Ok(())
+            }
+        }
+        let input = "position moves 2737772244262123677516643354\ngo depth 3\n".as_bytes();
+        let out = engine::run(Default::default(), 0, input, Buffered::default()).unwrap();
+        assert!(out.pending.is_empty());
+        assert_eq!(4, out.flushed.len());
+        assert!(out
+            .flushed
+            .iter()
+            .all(|line| line.matches('\n').count() == 1));
     }
     #[test]
     fn test_winning_move() {
         let pos2 = Position::parse("121212");
-        assert_eq!(true, pos2.is_winning_move(0));
+        assert!(pos2.is_winning_move(0));
     }
     #[test]
     fn test_has_winning_move() {
         let pos2 = Position::parse("112233");
         // let pos2 = Position::parse("121212");
         println!("\n##IGNORE##");
-        assert_eq!(true, pos2.has_winning_move());
+        assert!(pos2.has_winning_move());
     }
     fn test_solver(mut solver: Solver) {
         let pos = Position::parse("52753311433677442422121");
@@ -64,15 +355,19 @@ mod tests {
         let solver = Solver::new();
         test_solver(solver);
     }
-    fn opening_cmp(pos: &Position, book: &OpeningBook, solver: &mut Solver) {
-        if let Some(n) = book.get(&pos) {
-            let val = solver.solve(&pos, false);
-            let val2 = (n as isize) + Position::MIN_SCORE - 1;
-            println!("in book {val2}, solver: {val}");
-            assert_eq!(val, val2);
+    #[test]
+    fn test_solver_table_size() {
+        // too small and even sizes got rounded up, they gave wrong scores
+        for size in [0, 1 << 16, 1 << 20] {
+            let mut solver = Solver::with_table_size(Default::default(), size);
+            assert_eq!(10, solver.solve(&Position::parse("427631264721"), false));
+            assert_eq!(-9, solver.solve(&Position::parse("24657443354"), false));
         }
+        assert_eq!(
+            OptimizedTranspoisitionTable::MIN_SIZE,
+            OptimizedTranspoisitionTable::with_size(0).size()
+        );
     }
-
     #[test]
     fn test_pop_count() {
         let count = Position::pop_count(4, 0);
@@ -80,4 +375,637 @@ mod tests {
         let count = Position::pop_count(3, 0);
         assert_eq!(2, count);
     }
+    #[test]
+    fn test_book_header() {
+        let header = BookHeader::parse([7, 6, 14, 1, 1, 24]).expect("valid header");
+        assert_eq!(14, header.depth);
+        let res = BookHeader::parse([8, 6, 14, 1, 1, 24]);
+        assert!(matches!(res, Err(BookError::BoardSize { width: 8, .. })));
+        let res = BookHeader::parse([7, 6, 22, 1, 1, 24]);
+        assert!(matches!(res, Err(BookError::Depth(22))));
+        let res = BookHeader::parse([7, 6, 14, 2, 1, 24]);
+        assert!(matches!(res, Err(BookError::KeySize(2))));
+        let res = BookHeader::parse([7, 6, 14, 1, 1, 23]);
+        assert!(matches!(res, Err(BookError::LogSize(23))));
+    }
+    #[test]
+    fn test_book_truncated() {
+        let path = temp_path("truncated.book");
+        std::fs::write(&path, [7, 6, 14, 1, 1, 24, 0, 0, 0]).unwrap();
+        let res = OpeningBook::load(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(res, Err(BookError::Truncated)));
+    }
+    #[test]
+    fn test_book_round_trip() {
+        let mut book = OpeningBook::new();
+        let lines = [("", 1), ("4", -1), ("44", 2), ("437", -2), ("4455", 0)];
+        for (moves, score) in lines {
+            book.insert(&Position::parse(moves), score).unwrap();
+        }
+        let res = book.insert(&Position::parse("44556"), Position::MAX_SCORE + 1);
+        assert!(matches!(res, Err(BookError::Score(19))));
+        // too deep for the 44 bit book keys
+        let deep = Position::parse("2252576253462244111563365343671351441");
+        assert!(matches!(book.insert(&deep, 1), Err(BookError::Depth(37))));
+        let deepest = Position::parse("225257625346224411156");
+        assert_eq!(OpeningBook::MAX_DEPTH, deepest.moves);
+        assert!(deepest.key3() < 1 << 44);
+        let path = temp_path("round_trip.book");
+        let path = path.to_str().unwrap();
+        book.save(path).expect("book to save");
+        let loaded = OpeningBook::load(path).expect("book to load");
+        assert_eq!(4, loaded.depth());
+        assert!(book == loaded);
+        let mapped = OpeningBook::load_mapped(path).expect("book to map");
+        assert!(book == mapped);
+        let mut bytes = std::fs::read(path).unwrap();
+        assert!(book == OpeningBook::from_bytes(&bytes).expect("book from bytes"));
+        bytes.push(0);
+        let res = OpeningBook::from_bytes(&bytes);
+        assert!(matches!(res, Err(BookError::TrailingData)));
+        let res = OpeningBook::from_bytes(&bytes[..bytes.len() - 2]);
+        assert!(matches!(res, Err(BookError::Truncated)));
+        let res = OpeningBook::from_bytes(&bytes[..3]);
+        assert!(matches!(res, Err(BookError::Truncated)));
+        for (moves, score) in lines {
+            let n = loaded
+                .get(&Position::parse(moves))
+                .expect("position in book");
+            assert_eq!(score, n as isize + Position::MIN_SCORE - 1);
+            assert_eq!(Some(n), mapped.get(&Position::parse(moves)));
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+    #[test]
+    fn test_book_from_reader() {
+        let mut book = OpeningBook::new();
+        book.insert(&Position::parse("4455"), 3).unwrap();
+        let mut bytes = Vec::new();
+        book.write_to(&mut bytes).expect("book to write");
+        assert!(book == OpeningBook::from_bytes(&bytes).expect("book from bytes"));
+        // a stream handing out the book in pieces
+        let (head, tail) = bytes.split_at(1000);
+        let reader = std::io::Read::chain(head, tail);
+        let streamed = OpeningBook::from_reader(reader).expect("book from reader");
+        assert_eq!(Some(3), streamed.score(&Position::parse("4455")));
+        // a socket kept open after the book, or a stream with more after it
+        let mut reader = std::io::Read::chain(&bytes[..], std::io::repeat(7));
+        let streamed = OpeningBook::from_reader(&mut reader).expect("book from reader");
+        assert_eq!(Some(3), streamed.score(&Position::parse("4455")));
+        let mut next = [0];
+        std::io::Read::read_exact(&mut reader, &mut next).unwrap();
+        assert_eq!([7], next);
+        // a reader interrupted by signals now and then
+        struct Interrupted<'a>(&'a [u8], bool);
+        impl std::io::Read for Interrupted<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                self.1 = !self.1;
+                match self.1 {
+                    true => Err(std::io::ErrorKind::Interrupted.into()),
+                    false => self.0.read(buf),
+                }
+            }
+        }
+        let streamed = OpeningBook::from_reader(Interrupted(&bytes, false));
+        assert!(book == streamed.expect("book from reader"));
+        bytes[0] = 8;
+        let res = OpeningBook::from_bytes(&bytes);
+        assert!(matches!(res, Err(BookError::BoardSize { width: 8, .. })));
+        let res = OpeningBook::from_reader(std::io::empty());
+        assert!(matches!(res, Err(BookError::Truncated)));
+    }
+    #[test]
+    fn test_book_analyse() {
+        let mut book = OpeningBook::new();
+        let pos = Position::parse("4455");
+        assert_eq!(None, book.analyse(&pos));
+        let scores = [-2, 1, 0, -3, 0, 2, -1];
+        for (col, score) in scores.into_iter().enumerate() {
+            book.insert(&pos.next_pos(col), score).unwrap();
+        }
+        let expected: Vec<_> = scores.into_iter().map(|s| Some(-s)).collect();
+        assert_eq!(Some(expected), book.analyse(&pos));
+        assert_eq!(Some(3), book.best_move(&pos));
+        assert_eq!(Some(-3), book.score(&pos.next_pos(3)));
+    }
+    #[test]
+    fn test_book_coverage() {
+        let mut book = OpeningBook::new();
+        book.insert(&Position::parse("4"), -1).unwrap();
+        book.insert(&Position::parse("1"), 2).unwrap();
+        book.insert(&Position::parse("7"), 2).unwrap();
+        let stats = book_stats::coverage(&book, 2);
+        assert_eq!(
+            vec![1, 4, 25],
+            stats.iter().map(|s| s.reachable).collect::<Vec<_>>()
+        );
+        assert_eq!(0, stats[0].in_book);
+        assert_eq!(2, stats[1].in_book);
+        assert_eq!(Some(&1), stats[1].scores.get(&2));
+        assert_eq!(vec!["4", "7"], book_stats::sample(&book, 1, 5));
+    }
+    #[test]
+    fn test_book_verify() {
+        let mut book = OpeningBook::new();
+        book.insert(&Position::parse("271713432331713132"), -11)
+            .unwrap();
+        book.insert(&Position::parse("7532455277545526"), -9)
+            .unwrap();
+        book.insert(&Position::parse("47611556754127222"), 12)
+            .unwrap();
+        let lines = [
+            "271713432331713132",
+            "7532455277545526",
+            "47611556754127222",
+            "4455",
+        ];
+        let mismatches = book_stats::verify(&book, lines);
+        assert_eq!(1, mismatches.len());
+        assert_eq!("7532455277545526", mismatches[0].moves);
+        assert_eq!((-9, -10), (mismatches[0].book, mismatches[0].solver));
+    }
+    #[test]
+    fn test_batch() {
+        let mut solver = Solver::new();
+        let input = "4455\n\n4x\n52753311433677442422121\n";
+        let mut out = Vec::new();
+        batch::run(
+            &mut solver,
+            input.as_bytes(),
+            &mut out,
+            batch::Mode::Solve,
+            batch::Format::JsonLines,
+            false,
+        )
+        .unwrap();
+        let lines: Vec<serde_json::Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(3, lines.len());
+        assert_eq!(18, lines[0]["score"]);
+        assert_eq!("invalid position", lines[1]["error"]);
+        assert_eq!(8, lines[2]["score"]);
+
+        let mut out = Vec::new();
+        batch::run(
+            &mut solver,
+            "52753311433677442422121\n1111111\n".as_bytes(),
+            &mut out,
+            batch::Mode::Analyse,
+            batch::Format::Csv,
+            false,
+        )
+        .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!("pos,score,nodes,time_us,error", lines[0]);
+        assert!(lines[1].starts_with("52753311433677442422121,2;3;7;7;8;7;2,"));
+        assert_eq!("1111111,,,,invalid position", lines[2]);
+    }
+    #[test]
+    fn test_bencher_report() {
+        let path = temp_path("bench");
+        let path = path.to_str().unwrap();
+        std::fs::write(
+            path,
+            "52753311433677442422121 8\n\n2737772244262123677516643354 1\n",
+        )
+        .unwrap();
+        let report = bencher::run(
+            path,
+            Default::default(),
+            OptimizedTranspoisitionTable::MIN_SIZE,
+            false,
+        );
+        let report = report.expect("bench to run");
+        assert_eq!(2, report.count());
+        assert!(!report.passed());
+        let failures: Vec<_> = report.failures().collect();
+        assert_eq!(1, failures.len());
+        assert_eq!((1, 0), (failures[0].expected, failures[0].score));
+
+        std::fs::write(path, "4455 1\n4x55 2\n").unwrap();
+        let res = bencher::run(
+            path,
+            Default::default(),
+            OptimizedTranspoisitionTable::MIN_SIZE,
+            false,
+        );
+        std::fs::remove_file(path).unwrap();
+        // the bad line is skipped, the rest still runs
+        let report = res.expect("bench to run");
+        assert_eq!((1, false), (report.count(), report.passed()));
+        assert_eq!(2, report.skipped[0].0);
+        assert!(report
+            .to_string()
+            .contains("1 lines skipped:\n  line 2: invalid position"));
+    }
+    #[test]
+    fn test_bench_baseline() {
+        let dir = temp_path("suite");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("Test_A"), "52753311433677442422121 8\n").unwrap();
+        std::fs::write(dir.join("Test_B"), "2737772244262123677516643354 0\n").unwrap();
+        std::fs::write(dir.join("other"), "").unwrap();
+        let files = bencher::find_datasets(dir.to_str().unwrap(), "Test_?").unwrap();
+        assert_eq!(2, files.len());
+        let book = std::sync::Arc::new(OpeningBook::new());
+        let results = bencher::run_suite(
+            &files,
+            book,
+            OptimizedTranspoisitionTable::MIN_SIZE,
+            &[false, true],
+        )
+        .unwrap();
+        assert_eq!(4, results.len());
+        assert!(results.iter().all(|r| r.report.passed()));
+
+        let baseline = bencher::Baseline::from_results(&results);
+        let path = dir.join("baseline.json");
+        baseline.save(path.to_str().unwrap()).unwrap();
+        let loaded = bencher::Baseline::load(path.to_str().unwrap()).unwrap();
+        for bad in ["[{\"dataset\": ", "[{\"dataset\": \"Test_A\"}]"] {
+            std::fs::write(&path, bad).unwrap();
+            assert!(matches!(
+                bencher::Baseline::load(path.to_str().unwrap()),
+                Err(bencher::BenchError::Baseline(..))
+            ));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(baseline, loaded);
+        assert!(loaded.compare(&baseline, 0.1).is_empty());
+
+        let mut slower = baseline.clone();
+        slower.entries[0].mean_nodes *= 2.0;
+        let regressions = baseline.compare(&slower, 0.1);
+        assert_eq!(1, regressions.len());
+        assert_eq!("mean nodes", regressions[0].metric);
+    }
+    #[test]
+    fn test_bench_distribution() {
+        let d = bencher::Distribution::new((1..=200).rev().collect()).unwrap();
+        assert_eq!(
+            (1, 100, 180, 198, 200),
+            (d.min, d.median, d.p90, d.p99, d.max)
+        );
+        assert_eq!(None, bencher::Distribution::<u64>::new(vec![]));
+
+        let sample = |moves: &str, us| bencher::BenchSample {
+            moves: moves.to_string(),
+            expected: 0,
+            score: 0,
+            nodes: us,
+            time: std::time::Duration::from_micros(us),
+            passed: true,
+        };
+        let report = bencher::BenchReport {
+            samples: vec![
+                sample("1", 5),
+                sample("2", 300),
+                sample("3", 6),
+                sample("4", 20),
+            ],
+            ..Default::default()
+        };
+        let slowest: Vec<_> = report.slowest(2).iter().map(|s| s.moves.as_str()).collect();
+        assert_eq!(vec!["2", "4"], slowest);
+        let histogram = report.histogram();
+        assert_eq!((4, 2), histogram[0]);
+        assert_eq!((16, 1), histogram[2]);
+        assert_eq!((256, 1), *histogram.last().unwrap());
+        assert_eq!(7, histogram.len());
+    }
+    #[test]
+    fn test_generator() {
+        let mut solver = Solver::new();
+        // end stage positions are deduplicated up to symmetry
+        let deep = Position::parse("2252576253462244111563365343671351441");
+        let mirrored = Position::parse("6636312635426644777325523545217537447");
+        assert_eq!(mirrored, deep.mirror());
+        assert_eq!(deep, deep.mirror().mirror());
+        assert_eq!(deep.symmetric_key(), mirrored.symmetric_key());
+        assert_ne!(deep.symmetric_key(), deep.next_pos(6).symmetric_key());
+        let mut generator =
+            generator::Generator::with_stage(generator::Stage::End, generator::Difficulty::Easy, 7);
+        let mut out = Vec::new();
+        assert_eq!(5, generator.write(&mut solver, 5, 100, &mut out).unwrap());
+        let out = String::from_utf8(out).unwrap();
+        let mut keys = std::collections::HashSet::new();
+        for entry in dataset::Dataset::parse(&out).unwrap().entries {
+            let pos = entry.position;
+            assert!(generator::Stage::End.plies().contains(&pos.moves));
+            assert!(keys.insert(pos.symmetric_key()));
+            solver.reset();
+            assert_eq!(entry.score, solver.solve(&pos, false));
+            assert_eq!(entry.nodes, Some(solver.node_count));
+        }
+    }
+    #[test]
+    fn test_dataset_format() {
+        let s = "#! name: sample\n# comment\n\n4455 18 weak=1 analyse=-4,-3,18,2,2,18,- nodes=3 # quick win\n52753311433677442422121 8\n";
+        let data = dataset::Dataset::parse(s).unwrap();
+        assert_eq!(Some("sample"), data.header("name"));
+        assert_eq!(2, data.entries.len());
+        let entry = &data.entries[0];
+        assert_eq!(
+            (4, 18, Some(1), Some(3)),
+            (entry.line, entry.score, entry.weak, entry.nodes)
+        );
+        assert_eq!(Some(None), entry.analyse.as_ref().map(|a| a[6]));
+        assert_eq!(Some("quick win"), entry.comment.as_deref());
+        assert_eq!(1, data.entries[1].weak_score());
+        let written = data.to_string();
+        assert_eq!(
+            written,
+            dataset::Dataset::parse(&written).unwrap().to_string()
+        );
+
+        let res = dataset::Dataset::parse("4455 18\n4455 1 analyse=1,2\n");
+        assert!(matches!(res, Err(dataset::DatasetError::Parse(2, _))));
+        let (data, errors) = dataset::Dataset::parse_lenient("4x 1\n4455 18\n#! bad\n");
+        assert_eq!(1, data.entries.len());
+        let lines: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            vec![
+                "line 1: invalid position \"4x\"",
+                "line 3: invalid header \"#! bad\""
+            ],
+            lines
+        );
+    }
+    #[test]
+    fn test_dataset_end_easy() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/datasets/Test_L3_R1");
+        let data = dataset::Dataset::load(path).unwrap();
+        assert_eq!(1000, data.entries.len());
+        let mut solver = Solver::new();
+        for entry in data.entries.iter().take(20) {
+            solver.reset();
+            assert_eq!(entry.score, solver.solve(&entry.position, false));
+        }
+    }
+    #[test]
+    fn test_game() {
+        let mut g = game::Game::new();
+        for col in [0, 1, 0, 1, 0, 1] {
+            assert!(g.play(col));
+        }
+        assert_eq!(game::Status::Ongoing, g.status());
+        assert!(g.play(0));
+        assert_eq!(game::Status::Won(1), g.status());
+        assert!(!g.play(2));
+        assert_eq!(Some(0), g.undo());
+        assert_eq!(game::Status::Ongoing, g.status());
+        assert_eq!("121212", g.moves());
+        assert_eq!(Some(1), g.position().cell(0, 2));
+        assert_eq!(Some(2), g.position().cell(1, 0));
+        assert_eq!(None, g.position().cell(0, 3));
+    }
+    #[test]
+    fn test_play_terminal() {
+        let start = game::Game::from_moves("52753311433677442422121").unwrap();
+        let input = "h\nx\nq\n";
+        let mut out = Vec::new();
+        let mut ai = player::Level::Perfect.player(
+            Default::default(),
+            OptimizedTranspoisitionTable::MIN_SIZE,
+            rng::Rng::new(0),
+        );
+        let mut solver = Solver::new();
+        let status = game::play_terminal(
+            &mut solver,
+            ai.as_mut(),
+            start,
+            1,
+            input.as_bytes(),
+            &mut out,
+        );
+        assert_eq!(game::Status::Ongoing, status.unwrap());
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("you play X against perfect"));
+        assert!(out.contains("ai plays 5"));
+        // hints line up with the columns below them
+        let hints = [
+            "player: 1",
+            " -9  -9  -9  -9  -8  -9  -9",
+            "  .   .   .   .   .   .   .",
+        ];
+        assert!(out.contains(&hints.join("\n")));
+        assert!(out.contains("\n  1   2   3   4   5   6   7\n"));
+        assert!(out.contains("invalid move \"x\""));
+    }
+    #[test]
+    fn test_players() {
+        use player::{AiPlayer, HeuristicPlayer, RandomPlayer, SolverPlayer};
+        // player 1 completes the bottom row in column 4
+        let win = Position::parse("112233");
+        let pos = Position::parse("7532455277545526");
+        let mut random = RandomPlayer::new(rng::Rng::new(1));
+        assert_eq!(Some(3), random.choose(&win));
+        assert!(random.choose(&pos).is_some_and(|col| pos.can_play(col)));
+
+        let mut heuristic = HeuristicPlayer::new(Solver::new(), 2, rng::Rng::new(1));
+        assert_eq!(Some(3), heuristic.choose(&win));
+        // has to block the vertical three in column 1
+        assert_eq!(Some(0), heuristic.choose(&Position::parse("1212152")));
+
+        let perfect = Solver::new().analyse(&pos, false);
+        let best = solver::best_move(&perfect).unwrap();
+        let mut cold = SolverPlayer::new(Solver::new(), 0.0, rng::Rng::new(1));
+        assert_eq!(Some(best), cold.choose(&pos));
+        // a hot player still plays every move it picks
+        let mut hot = SolverPlayer::new(Solver::new(), 100.0, rng::Rng::new(1));
+        let picks: Vec<_> = (0..20).flat_map(|_| hot.choose(&pos)).collect();
+        assert!(picks.iter().all(|&col| pos.can_play(col)));
+        assert!(picks.iter().any(|&col| col != best));
+
+        // a player insisting on a full column ends the game with an error
+        struct Stubborn;
+        impl AiPlayer for Stubborn {
+            fn name(&self) -> String {
+                "stubborn".to_string()
+            }
+            fn choose(&mut self, _: &Position) -> Option<usize> {
+                Some(0)
+            }
+        }
+        let start = game::Game::from_moves("111111").unwrap();
+        let res = game::play_ai(start, [&mut Stubborn, &mut random]);
+        let err = res.unwrap_err().to_string();
+        assert_eq!("stubborn chose column 1 after \"111111\"", err);
+    }
+    #[test]
+    fn test_evaluate() {
+        // mirrored positions evaluate the same
+        let pos = Position::parse("4453");
+        assert_eq!(pos.evaluate(), Position::parse("4435").evaluate());
+        // a piece in the centre beats one on the edge
+        assert!(Position::parse("1").evaluate() > Position::parse("4").evaluate());
+        // player 2 to move against an open three of player 1
+        assert!(Position::parse("44556").evaluate() < 0);
+        for moves in [
+            "",
+            "52753311433677442422121",
+            "1233722555341451114725221333",
+        ] {
+            assert!(Position::parse(moves).evaluate().abs() < Position::EVAL_BOUND);
+        }
+    }
+    #[test]
+    fn test_solve_depth() {
+        let mut solver = Solver::new();
+        // deep enough to reach the end of the game the scores are exact
+        for (moves, score) in [
+            ("2252576253462244111563365343671351441", -1),
+            ("7422341735647741166133573473242566", 1),
+            ("65214673556155731566316327373221417", -1),
+            ("2737772244262123677516643354", 0),
+        ] {
+            let pos = Position::parse(moves);
+            let depth = Position::WIDTH * Position::HEIGHT - pos.moves;
+            let result = solver.solve_depth(&pos, depth);
+            assert_eq!((Some(score), true), (result.score(), result.exact));
+        }
+        // a static guess otherwise, one of 0 is no proven draw
+        let guess = solver.solve_depth(&Position::parse("44"), 2);
+        assert_eq!((0, None), (guess.value, guess.score()));
+        assert_eq!(
+            None,
+            solver.solve_depth(&Position::parse("4453"), 2).score()
+        );
+        let scores = solver.analyse_depth(&Position::parse("112233"), 1);
+        assert_eq!(Some(18), scores[3].and_then(|s| s.score()));
+        assert_eq!(Some(3), solver::best_move(&scores));
+    }
+    #[test]
+    fn test_mcts() {
+        use mcts::{Mcts, Playout};
+        use player::AiPlayer;
+        for playout in [Playout::Random, Playout::Heuristic] {
+            let mut mcts = Mcts::new(500, playout, rng::Rng::new(3));
+            assert_eq!(Some(3), mcts.choose(&Position::parse("112233")));
+            // player 2 has to block column 1
+            assert_eq!(Some(0), mcts.choose(&Position::parse("1212152")));
+        }
+        // the tree two plies further down is kept
+        let mut mcts = Mcts::new(500, Playout::Heuristic, rng::Rng::new(3));
+        let game = game::Game::from_moves("4453").unwrap();
+        let col = mcts.choose(&game.position()).unwrap();
+        let reply = game.position().next_pos(col).next_pos(3);
+        mcts.choose(&reply);
+        assert!(mcts.visits().iter().sum::<u32>() > 500);
+
+        // a match against the solver from a late position
+        let start = game::Game::from_moves("2252576253462244111563365343671351441").unwrap();
+        let mut solver = player::SolverPlayer::new(Solver::new(), 0.0, rng::Rng::new(0));
+        let end = game::play_ai(start, [&mut mcts, &mut solver]).unwrap();
+        assert_ne!(game::Status::Ongoing, end.status());
+    }
+    #[test]
+    fn test_tournament() {
+        use tournament::{MatchResult, Openings};
+        let result = MatchResult {
+            wins: 30,
+            draws: 40,
+            losses: 30,
+            ..Default::default()
+        };
+        assert_eq!(0.0, result.elo());
+        assert!(result.elo_error() > 0.0 && result.elo_error() < 100.0);
+        let better = MatchResult {
+            wins: 60,
+            draws: 20,
+            losses: 20,
+            ..Default::default()
+        };
+        assert!((better.elo() - 147.2).abs() < 0.1);
+
+        let mut rng = rng::Rng::new(7);
+        let starts = tournament::openings(Openings::Random(6), 3, &OpeningBook::new(), &mut rng);
+        assert!(starts.iter().all(|g| g.history().len() <= 6));
+        let mut a = player::HeuristicPlayer::new(Solver::new(), 1, rng::Rng::new(1));
+        let mut b = player::RandomPlayer::new(rng::Rng::new(2));
+        let mut log = Vec::new();
+        let result = tournament::run_match(&mut a, &mut b, &starts, &mut log).unwrap();
+        assert_eq!(6, result.games());
+        let log = String::from_utf8(log).unwrap();
+        assert_eq!(6, log.matches("[Round ").count());
+        assert!(log.contains("[Player1 \"random\"]"));
+        assert!(log.contains("[Player2 \"heuristic depth 1\"]"));
+        let records = record::GameRecord::parse(&log).unwrap();
+        assert_eq!(6, records.len());
+        assert!(records
+            .iter()
+            .all(|r| r.game().unwrap().status() != game::Status::Ongoing));
+    }
+    #[test]
+    fn test_game_record() {
+        use record::{GameRecord, RecordError};
+        let game = game::Game::from_moves("1212121").unwrap();
+        let mut record = GameRecord::from_game(&game);
+        record.set_tag("Player1", "say \"hi\"");
+        record.moves[0].eval = Some(-3);
+        record.moves[6].comment = Some("four in a row".to_string());
+        let text = record.to_string();
+        assert!(text.starts_with("[Date \""));
+        assert!(text.contains("[Result \"1-0\"]"));
+        assert!(text.contains("1. 1 {[%eval -3]} 2 2. 1 2"));
+        assert!(text.ends_with("4. 1 {four in a row} 1-0\n"));
+        let parsed = GameRecord::parse(&format!("{text}\n{text}")).unwrap();
+        assert_eq!(vec![record.clone(), record], parsed);
+        assert_eq!(game.position(), parsed[0].position().unwrap());
+
+        // move numbers are optional
+        let bare = &GameRecord::parse("4 4 {[%eval 0] even} 5\n*").unwrap()[0];
+        assert_eq!(Position::parse("445"), bare.position().unwrap());
+        assert_eq!(Some("even".to_string()), bare.moves[1].comment);
+        assert!(matches!(
+            GameRecord::parse("4 8"),
+            Err(RecordError::Parse(1, _))
+        ));
+        assert!(matches!(
+            GameRecord::parse("[Width 7]"),
+            Err(RecordError::Parse(1, _))
+        ));
+        let big = &GameRecord::parse("[Width \"8\"]\n1 2").unwrap()[0];
+        assert!(matches!(
+            big.game(),
+            Err(RecordError::BoardSize { width: 8, .. })
+        ));
+        let full = &GameRecord::parse("1 1 1 1 1 1 1").unwrap()[0];
+        assert!(matches!(full.game(), Err(RecordError::IllegalMove(6))));
+        // a result ends the game without any tags
+        let games = GameRecord::parse("4 4 1-0\n5 5 *").unwrap();
+        let moves: Vec<_> = games.iter().map(|r| r.moves.len()).collect();
+        assert_eq!(vec![2, 2], moves);
+        assert_eq!(Some("*"), games[1].tag("Result"));
+        assert_eq!(2, GameRecord::parse("4 4 0-1 5 *").unwrap().len());
+
+        let day = |days: u64| std::time::UNIX_EPOCH + std::time::Duration::from_secs(days * 86400);
+        assert_eq!("1970.01.01", record::date_tag(day(0)));
+        assert_eq!("2000.02.29", record::date_tag(day(11016)));
+        assert_eq!("2024.05.01", record::date_tag(day(19844)));
+    }
+    #[test]
+    fn test_solver_stop() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        let flag = std::sync::Arc::new(AtomicBool::new(true));
+        let mut solver = Solver::new();
+        solver.set_stop_flag(flag.clone());
+        // would take ages without the flag
+        solver.solve(&Position::new(), false);
+        assert!(solver.stopped());
+        assert!(solver.node_count <= 4096);
+
+        flag.store(false, Ordering::Relaxed);
+        let pos = Position::parse("52753311433677442422121");
+        assert_eq!(8, solver.solve(&pos, false));
+        assert!(!solver.stopped());
+        let pv = solver.principal_variation(&pos, false);
+        assert_eq!(Some(&4), pv.first());
+        assert!(pv.len() % 2 == 1);
+    }
 }
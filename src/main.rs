@@ -1,17 +1,720 @@
-use connect_4_ai::solver::Solver;
-use connect_4_ai::{bencher, position, Position};
-// use connect_4_ai::{NaiveTranspositionTable,Entry, Position};
-use connect_4_ai::position::OpeningBook;
+use clap::{Parser, Subcommand, ValueEnum};
+use connect_4_ai::generator::{Difficulty, Generator, Stage};
+use connect_4_ai::player::Level;
+use connect_4_ai::position::{Notation, OpeningBook};
+use connect_4_ai::record::GameRecord;
+use connect_4_ai::rng::Rng;
+use connect_4_ai::solver::{self, Solver};
+use connect_4_ai::tournament::{self, Openings};
+use connect_4_ai::transposition_table::OptimizedTranspoisitionTable;
+use connect_4_ai::{batch, bencher, book_stats, game, Position};
+use serde_json::json;
+use std::io;
+use std::process::ExitCode;
 use std::sync::Arc;
 
-fn main() {
-    // bencher::run("datasets/Test_L1_R2", false);
-    let book = Arc::new(OpeningBook::load("7x6.book").expect("loaded"));
-    let mut solver = Solver::with_opening_book(book);
-    let moves = "57";
-    let weak = false;
-    let pos = Position::parse(moves);
-    let scores = solver.analyse(&pos, weak);
-    let sol = solver.solve(&pos, weak);
-    println!("pos: {moves}, scores: {scores:?}, solution: {sol}");
+#[derive(Parser)]
+#[command(about = "perfect connect 4 solver")]
+struct Cli {
+    /// opening book file, skipped with a warning if it can't be loaded
+    #[arg(long, global = true, default_value = "7x6.book")]
+    book: String,
+    /// run without an opening book
+    #[arg(long, global = true)]
+    no_book: bool,
+    /// transposition table entries (5 bytes each), rounded up to a prime
+    /// of at least 131101
+    #[arg(long, global = true, default_value_t = OptimizedTranspoisitionTable::SIZE)]
+    table_size: usize,
+    /// only compute win/draw/loss instead of the exact score
+    #[arg(long, global = true)]
+    weak: bool,
+    /// only search this many plies and guess the rest, exact scores are
+    /// marked, guesses beyond +-1000 are proven wins or losses
+    /// (1000 + at least the score)
+    #[arg(long, global = true)]
+    depth: Option<usize>,
+    /// how positions are written, auto only accepts positions that
+    /// read the same in every notation they are valid in
+    #[arg(long, global = true, value_enum, default_value_t = NotationArg::One)]
+    notation: NotationArg,
+    #[arg(long, global = true, value_enum, default_value_t = Format::Text)]
+    format: Format,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Text,
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum NotationArg {
+    /// columns 1-7
+    One,
+    /// columns 0-6
+    Zero,
+    /// columns a-g
+    Letters,
+    /// 1-based moves, optionally followed by a score
+    Reference,
+    /// 9 character base64 key
+    Key,
+    Auto,
+}
+impl NotationArg {
+    fn notation(self) -> Option<Notation> {
+        match self {
+            Self::One => Some(Notation::OneBased),
+            Self::Zero => Some(Notation::ZeroBased),
+            Self::Letters => Some(Notation::Letters),
+            Self::Reference => Some(Notation::Reference),
+            Self::Key => Some(Notation::Key),
+            Self::Auto => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum BatchFormat {
+    /// "pos score nodes time_us"
+    Text,
+    Csv,
+    Jsonl,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum GenStage {
+    Begin,
+    Middle,
+    End,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum GenDifficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum PlayLevel {
+    /// random moves, but takes wins
+    Random,
+    /// 2 plies of heuristic search
+    Easy,
+    /// 6 plies of heuristic search
+    Medium,
+    /// the solver, sometimes picking a worse move
+    Hard,
+    Perfect,
+    /// monte carlo tree search, see --playouts
+    Mcts,
+}
+impl PlayLevel {
+    fn level(self, playouts: usize) -> Level {
+        match self {
+            Self::Random => Level::Random,
+            Self::Easy => Level::Easy,
+            Self::Medium => Level::Medium,
+            Self::Hard => Level::Hard,
+            Self::Perfect => Level::Perfect,
+            Self::Mcts => Level::Mcts(playouts),
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum MatchOpenings {
+    Empty,
+    /// random moves that don't decide the game
+    Random,
+    /// random among the book's best moves
+    Book,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// score of each position, moves are columns 1-7
+    Solve { positions: Vec<String> },
+    /// score of every column of each position
+    Analyse { positions: Vec<String> },
+    /// best move of a position
+    Bestmove {
+        #[arg(default_value = "")]
+        position: String,
+    },
+    /// play against the solver in the terminal
+    Play {
+        /// let the ai make the first move
+        #[arg(long)]
+        ai_first: bool,
+        /// moves already played before the game starts
+        #[arg(long, default_value = "")]
+        position: String,
+        #[arg(long, value_enum, default_value_t = PlayLevel::Perfect)]
+        level: PlayLevel,
+        /// playouts per move of the mcts level
+        #[arg(long, default_value_t = 10_000)]
+        playouts: usize,
+        /// random seed, taken from the clock if missing
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+    /// play games between two engines with swapped colours and estimate elo
+    Match {
+        #[arg(value_enum)]
+        first: PlayLevel,
+        #[arg(value_enum)]
+        second: PlayLevel,
+        /// games are played in pairs, one per colour
+        #[arg(long, default_value_t = 20)]
+        games: usize,
+        #[arg(long, value_enum, default_value_t = MatchOpenings::Random)]
+        openings: MatchOpenings,
+        /// plies of each opening
+        #[arg(long, default_value_t = 4)]
+        plies: usize,
+        /// playouts per move of mcts engines
+        #[arg(long, default_value_t = 10_000)]
+        playouts: usize,
+        /// random seed, taken from the clock if missing
+        #[arg(long)]
+        seed: Option<u64>,
+        /// file for the move logs of every game
+        #[arg(long)]
+        log: Option<String>,
+    },
+    /// solve "moves score" dataset files in weak and strong mode and report timings
+    Bench {
+        /// dataset files, every file of --dir matching --pattern if empty
+        files: Vec<String>,
+        #[arg(long, default_value = "datasets")]
+        dir: String,
+        /// file name pattern, `*` and `?` are wildcards
+        #[arg(long, default_value = "*")]
+        pattern: String,
+        /// only run strong mode, --weak only runs weak mode
+        #[arg(long)]
+        strong: bool,
+        /// write the results to a json baseline file
+        #[arg(long)]
+        save: Option<String>,
+        /// compare the results with a json baseline file
+        #[arg(long)]
+        baseline: Option<String>,
+        /// allowed slowdown against the baseline, in percent
+        #[arg(long, default_value_t = 10.0)]
+        threshold: f64,
+        /// print percentiles, the slowest positions and a time histogram
+        #[arg(long)]
+        stats: bool,
+        /// number of slowest positions listed by --stats
+        #[arg(long, default_value_t = 5)]
+        slowest: usize,
+    },
+    /// read one position per line from stdin and stream the results
+    Batch {
+        /// score every column instead of the position
+        #[arg(long)]
+        analyse: bool,
+        #[arg(long, value_enum, default_value_t = BatchFormat::Text)]
+        output: BatchFormat,
+    },
+    /// uci-like engine protocol on stdin and stdout for guis and arenas
+    Engine,
+    /// write a new "moves score" dataset from random games
+    Generate {
+        #[arg(long, value_enum, default_value_t = GenStage::End)]
+        stage: GenStage,
+        #[arg(long, value_enum, default_value_t = GenDifficulty::Easy)]
+        difficulty: GenDifficulty,
+        #[arg(long, default_value_t = 1000)]
+        count: usize,
+        /// random seed, taken from the clock if missing
+        #[arg(long)]
+        seed: Option<u64>,
+        /// games tried per position before giving up
+        #[arg(long, default_value_t = 10_000)]
+        attempts: usize,
+        /// output file, stdout if missing
+        #[arg(long)]
+        output: Option<String>,
+    },
+    /// replay the games of a record file and print where they ended
+    Replay {
+        file: String,
+        /// only replay this game, counted from 1
+        #[arg(long)]
+        game: Option<usize>,
+    },
+    /// http json api with /solve, /analyse and /bestmove
+    #[cfg(feature = "server")]
+    Serve {
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: String,
+        /// solver threads, each with its own --table-size table
+        #[arg(long, default_value_t = 4)]
+        workers: usize,
+        /// requests taking longer get a 503
+        #[arg(long, default_value_t = 10_000)]
+        timeout_ms: u64,
+        /// responses kept in the cache
+        #[arg(long, default_value_t = 10_000)]
+        cache: usize,
+    },
+    /// full-screen analysis board
+    #[cfg(feature = "tui")]
+    Tui {
+        /// moves already played
+        #[arg(default_value = "")]
+        position: String,
+    },
+    /// write a position in another notation
+    Convert {
+        position: String,
+        #[arg(long, value_enum)]
+        to: NotationArg,
+    },
+    /// opening book statistics
+    Book {
+        #[command(subcommand)]
+        command: BookCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum BookCommand {
+    /// reachable and stored positions per ply
+    Coverage {
+        #[arg(long, default_value_t = 8)]
+        depth: usize,
+    },
+    /// compare sampled book entries with the solver
+    Verify {
+        #[arg(long, default_value_t = 12)]
+        ply: usize,
+        #[arg(long, default_value_t = 20)]
+        count: usize,
+    },
+    /// moves the book plays on its own from a position
+    Line {
+        #[arg(default_value = "")]
+        position: String,
+    },
+}
+
+fn load_book(cli: &Cli) -> Arc<OpeningBook> {
+    if cli.no_book {
+        return Arc::new(OpeningBook::new());
+    }
+    match OpeningBook::load_mapped(&cli.book) {
+        Ok(book) => Arc::new(book),
+        Err(e) => {
+            eprintln!("warning: {}: {e}, running without opening book", cli.book);
+            Arc::new(OpeningBook::new())
+        }
+    }
+}
+
+fn parse(notation: NotationArg, moves: &str) -> Result<Position, String> {
+    let pos = match notation.notation() {
+        Some(notation) => Position::parse_notation(moves, notation),
+        None => Position::parse_any(moves),
+    };
+    pos.ok_or_else(|| format!("invalid position: {moves:?}"))
+}
+
+fn parse_game(notation: NotationArg, moves: &str) -> Result<game::Game, String> {
+    game::Game::from_position(&parse(notation, moves)?)
+        .ok_or_else(|| format!("no game reaches {moves:?}"))
+}
+
+fn print(format: Format, text: String, json: serde_json::Value) {
+    match format {
+        Format::Text => println!("{text}"),
+        Format::Json => println!("{json}"),
+    }
+}
+
+fn run(cli: Cli) -> Result<(), String> {
+    let book = load_book(&cli);
+    let (weak, format, notation) = (cli.weak, cli.format, cli.notation);
+    let solver = || Solver::with_table_size(Arc::clone(&book), cli.table_size);
+    match &cli.command {
+        Command::Solve { positions } => {
+            let mut solver = solver();
+            for moves in positions {
+                let pos = parse(notation, moves)?;
+                solver.reset();
+                let (score, exact) = match cli.depth {
+                    Some(depth) => {
+                        let score = solver.solve_depth(&pos, depth);
+                        (score.score().unwrap_or(score.value), score.exact)
+                    }
+                    None => (solver.solve(&pos, weak), true),
+                };
+                let nodes = solver.node_count;
+                print(
+                    format,
+                    match cli.depth {
+                        Some(_) => {
+                            format!("pos: {moves}, score: {score}, exact: {exact}, nodes: {nodes}")
+                        }
+                        None => format!("pos: {moves}, score: {score}, nodes: {nodes}"),
+                    },
+                    json!({ "pos": moves, "score": score, "exact": exact, "nodes": nodes }),
+                );
+            }
+        }
+        Command::Analyse { positions } => {
+            let mut solver = solver();
+            for moves in positions {
+                let pos = parse(notation, moves)?;
+                let (scores, exact): (Vec<_>, Vec<_>) = match cli.depth {
+                    Some(depth) => solver
+                        .analyse_depth(&pos, depth)
+                        .into_iter()
+                        .map(|s| {
+                            let score = s.map(|s| s.score().unwrap_or(s.value));
+                            (score, s.map(|s| s.exact))
+                        })
+                        .unzip(),
+                    None => {
+                        let scores = solver.analyse(&pos, weak);
+                        let exact = scores.iter().map(|s| s.map(|_| true)).collect();
+                        (scores, exact)
+                    }
+                };
+                let text = match cli.depth {
+                    Some(_) => format!("pos: {moves}, scores: {scores:?}, exact: {exact:?}"),
+                    None => format!("pos: {moves}, scores: {scores:?}"),
+                };
+                print(
+                    format,
+                    text,
+                    json!({ "pos": moves, "scores": scores, "exact": exact }),
+                );
+            }
+        }
+        Command::Play {
+            ai_first,
+            position,
+            level,
+            playouts,
+            seed,
+        } => {
+            let start = parse_game(notation, position)?;
+            let human = match ai_first {
+                true => 3 - start.position().current_player(),
+                false => start.position().current_player(),
+            };
+            let rng = seed.map_or_else(Rng::from_time, Rng::new);
+            let level = level.level(*playouts);
+            let mut ai = level.player(Arc::clone(&book), cli.table_size, rng);
+            game::play_terminal(
+                &mut solver(),
+                ai.as_mut(),
+                start,
+                human,
+                io::stdin().lock(),
+                io::stdout().lock(),
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        Command::Match {
+            first,
+            second,
+            games,
+            openings,
+            plies,
+            playouts,
+            seed,
+            log,
+        } => {
+            let mut rng = seed.map_or_else(Rng::from_time, Rng::new);
+            let kind = match openings {
+                MatchOpenings::Empty => Openings::Empty,
+                MatchOpenings::Random => Openings::Random(*plies),
+                MatchOpenings::Book => Openings::Book(*plies),
+            };
+            let starts = tournament::openings(kind, games.div_ceil(2), &book, &mut rng);
+            let player = |level: PlayLevel, rng| {
+                level
+                    .level(*playouts)
+                    .player(Arc::clone(&book), cli.table_size, rng)
+            };
+            let mut a = player(*first, Rng::new(rng.next_u64()));
+            let mut b = player(*second, Rng::new(rng.next_u64()));
+            let log: Box<dyn io::Write> = match log {
+                Some(path) => Box::new(io::BufWriter::new(
+                    std::fs::File::create(path).map_err(|e| format!("{path}: {e}"))?,
+                )),
+                None => Box::new(io::sink()),
+            };
+            let result = tournament::run_match(a.as_mut(), b.as_mut(), &starts, log)
+                .map_err(|e| e.to_string())?;
+            print(
+                format,
+                result.to_string(),
+                json!({
+                    "first": result.names[0],
+                    "second": result.names[1],
+                    "wins": result.wins,
+                    "draws": result.draws,
+                    "losses": result.losses,
+                    "score": result.score(),
+                    "elo": result.elo(),
+                    "elo_error": result.elo_error(),
+                }),
+            );
+        }
+        Command::Convert { position, to } => {
+            let pos = parse(notation, position)?;
+            let notations = match to.notation() {
+                Some(notation) => vec![notation],
+                None => Notation::ALL.to_vec(),
+            };
+            for to in notations {
+                let code = pos
+                    .format(to)
+                    .ok_or_else(|| format!("no game reaches {position:?}"))?;
+                print(
+                    format,
+                    format!("{to:?}: {code}"),
+                    json!({ "notation": format!("{to:?}"), "pos": code }),
+                );
+            }
+        }
+        Command::Replay { file, game } => {
+            let records = GameRecord::load(file).map_err(|e| format!("{file}: {e}"))?;
+            for (i, record) in records.iter().enumerate() {
+                if game.is_some_and(|n| n != i + 1) {
+                    continue;
+                }
+                let pos = record
+                    .position()
+                    .map_err(|e| format!("{file}: game {}: {e}", i + 1))?;
+                for (key, value) in &record.tags {
+                    println!("{key}: {value}");
+                }
+                println!("{pos}\n");
+            }
+        }
+        #[cfg(feature = "server")]
+        Command::Serve {
+            addr,
+            workers,
+            timeout_ms,
+            cache,
+        } => {
+            let config = connect_4_ai::server::Config {
+                workers: *workers,
+                table_size: cli.table_size,
+                timeout: std::time::Duration::from_millis(*timeout_ms),
+                cache_size: *cache,
+            };
+            eprintln!("listening on http://{addr}");
+            connect_4_ai::server::serve(addr, book, config).map_err(|e| format!("{addr}: {e}"))?;
+        }
+        #[cfg(feature = "tui")]
+        Command::Tui { position } => {
+            let start = parse_game(notation, position)?;
+            connect_4_ai::tui::run(book, cli.table_size, start, weak).map_err(|e| e.to_string())?;
+        }
+        Command::Bestmove { position } => {
+            let pos = parse(notation, position)?;
+            let mut solver = solver();
+            let col = match cli.depth {
+                Some(depth) => solver::best_move(&solver.analyse_depth(&pos, depth)),
+                None => solver.best_move(&pos, weak),
+            };
+            let col = col.ok_or_else(|| format!("no legal move in {position:?}"))?;
+            print(
+                format,
+                format!("pos: {position}, move: {}", col + 1),
+                json!({ "pos": position, "move": col + 1 }),
+            );
+        }
+        Command::Bench {
+            files,
+            dir,
+            pattern,
+            strong,
+            save,
+            baseline,
+            threshold,
+            stats,
+            slowest,
+        } => {
+            let files = match files.is_empty() {
+                true => bencher::find_datasets(dir, pattern).map_err(|e| e.to_string())?,
+                false => files.clone(),
+            };
+            if files.is_empty() {
+                return Err(format!("no dataset in {dir} matches {pattern:?}"));
+            }
+            let modes = match (*strong, weak) {
+                (true, false) => vec![false],
+                (false, true) => vec![true],
+                _ => vec![false, true],
+            };
+            let results = bencher::run_suite(&files, book, cli.table_size, &modes)
+                .map_err(|e| e.to_string())?;
+            for r in results.iter().filter(|r| !r.report.passed()) {
+                println!(
+                    "{} {}: {}",
+                    r.dataset,
+                    if r.weak { "weak" } else { "strong" },
+                    r.report
+                );
+            }
+            if *stats {
+                for r in &results {
+                    let mode = if r.weak { "weak" } else { "strong" };
+                    println!("{} {mode}:\n{}", r.dataset, r.report.format_stats(*slowest));
+                }
+            }
+            print!("{}", bencher::format_table(&results));
+
+            let current = bencher::Baseline::from_results(&results);
+            if let Some(path) = save {
+                current.save(path).map_err(|e| e.to_string())?;
+            }
+            let regressions = match baseline {
+                Some(path) => bencher::Baseline::load(path)
+                    .map_err(|e| e.to_string())?
+                    .compare(&current, threshold / 100.0),
+                None => Vec::new(),
+            };
+            for r in &regressions {
+                println!("regression: {r}");
+            }
+            if results.iter().any(|r| !r.report.passed()) {
+                return Err("wrong scores".to_string());
+            }
+            if !regressions.is_empty() {
+                return Err(format!("{} regressions", regressions.len()));
+            }
+        }
+        Command::Batch { analyse, output } => {
+            let mode = match analyse {
+                true => batch::Mode::Analyse,
+                false => batch::Mode::Solve,
+            };
+            let output_format = match output {
+                BatchFormat::Text => batch::Format::Text,
+                BatchFormat::Csv => batch::Format::Csv,
+                BatchFormat::Jsonl => batch::Format::JsonLines,
+            };
+            batch::run(
+                &mut solver(),
+                io::stdin().lock(),
+                io::stdout().lock(),
+                mode,
+                output_format,
+                weak,
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        Command::Engine => {
+            connect_4_ai::engine::run(book, cli.table_size, io::stdin().lock(), io::stdout())
+                .map_err(|e| e.to_string())?;
+        }
+        Command::Generate {
+            stage,
+            difficulty,
+            count,
+            seed,
+            attempts,
+            output,
+        } => {
+            let stage = match stage {
+                GenStage::Begin => Stage::Begin,
+                GenStage::Middle => Stage::Middle,
+                GenStage::End => Stage::End,
+            };
+            let difficulty = match difficulty {
+                GenDifficulty::Easy => Difficulty::Easy,
+                GenDifficulty::Medium => Difficulty::Medium,
+                GenDifficulty::Hard => Difficulty::Hard,
+            };
+            let seed = seed.unwrap_or_else(|| Rng::from_time().next_u64());
+            let mut generator = Generator::with_stage(stage, difficulty, seed);
+            let out: Box<dyn io::Write> = match output {
+                Some(path) => Box::new(io::BufWriter::new(
+                    std::fs::File::create(path).map_err(|e| format!("{path}: {e}"))?,
+                )),
+                None => Box::new(io::stdout().lock()),
+            };
+            let written = generator
+                .write(&mut solver(), *count, *attempts, out)
+                .map_err(|e| e.to_string())?;
+            if written < *count {
+                return Err(format!("only found {written} of {count} positions"));
+            }
+        }
+        Command::Book { command } => match command {
+            BookCommand::Coverage { depth } => {
+                for s in book_stats::coverage(&book, *depth) {
+                    print(
+                        format,
+                        format!(
+                            "ply: {}, reachable: {}, in book: {}, scores: {:?}",
+                            s.ply, s.reachable, s.in_book, s.scores
+                        ),
+                        json!({
+                            "ply": s.ply,
+                            "reachable": s.reachable,
+                            "in_book": s.in_book,
+                            "scores": s.scores.iter().collect::<Vec<_>>(),
+                        }),
+                    );
+                }
+            }
+            BookCommand::Verify { ply, count } => {
+                let lines = book_stats::sample(&book, *ply, *count);
+                let mismatches = book_stats::verify(&book, lines.iter().map(|s| s.as_str()));
+                for m in &mismatches {
+                    print(
+                        format,
+                        format!("pos: {}, book: {}, solver: {}", m.moves, m.book, m.solver),
+                        json!({ "pos": m.moves, "book": m.book, "solver": m.solver }),
+                    );
+                }
+                if !mismatches.is_empty() {
+                    return Err(format!(
+                        "{} of {} book entries are wrong",
+                        mismatches.len(),
+                        lines.len()
+                    ));
+                }
+            }
+            BookCommand::Line { position } => {
+                let line: Vec<_> = book
+                    .line(&parse(notation, position)?)
+                    .iter()
+                    .map(|c| c + 1)
+                    .collect();
+                print(
+                    format,
+                    format!("pos: {position}, line: {line:?}"),
+                    json!({ "pos": position, "line": line }),
+                );
+            }
+        },
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
 }
@@ -0,0 +1,225 @@
+use super::*;
+use player::AiPlayer;
+use rng::Rng;
+
+// how a playout picks its moves
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Playout {
+    // any legal move, taking a win on the board
+    Random,
+    // a win on the board or else a move from possible_non_loosing_moves
+    Heuristic,
+}
+
+struct Node {
+    pos: Position,
+    // column played to get here from the parent
+    col: usize,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    untried: Vec<usize>,
+    visits: u32,
+    // summed results for the player who moved into this node, 1 win, 0.5 draw
+    value: f64,
+    // known result for that player once the game is over
+    terminal: Option<f64>,
+}
+impl Node {
+    fn new(pos: Position, col: usize, parent: Option<usize>, terminal: Option<f64>) -> Self {
+        let untried = match terminal {
+            Some(_) => Vec::new(),
+            None => (0..Position::WIDTH).filter(|&c| pos.can_play(c)).collect(),
+        };
+        Self {
+            pos,
+            col,
+            parent,
+            children: Vec::new(),
+            untried,
+            visits: 0,
+            value: 0.0,
+            terminal,
+        }
+    }
+}
+
+// monte carlo tree search with UCT selection, the tree below the
+// position reached two plies later is kept for the next move
+pub struct Mcts {
+    pub playouts: usize,
+    pub playout: Playout,
+    pub exploration: f64,
+    nodes: Vec<Node>,
+    rng: Rng,
+}
+
+impl Mcts {
+    pub fn new(playouts: usize, playout: Playout, rng: Rng) -> Self {
+        Self {
+            playouts,
+            playout,
+            exploration: std::f64::consts::SQRT_2,
+            nodes: Vec::new(),
+            rng,
+        }
+    }
+    // nodes kept from earlier searches
+    pub fn tree_size(&self) -> usize {
+        self.nodes.len()
+    }
+    // visits of every column at the root after the last search
+    pub fn visits(&self) -> Vec<u32> {
+        let mut visits = vec![0; Position::WIDTH];
+        if let Some(root) = self.nodes.first() {
+            for &child in &root.children {
+                visits[self.nodes[child].col] = self.nodes[child].visits;
+            }
+        }
+        visits
+    }
+
+    // keeps the subtree of pos if it is the root or up to two plies below
+    fn reroot(&mut self, pos: &Position) {
+        let mut found = None;
+        if let Some(root) = self.nodes.first() {
+            let mut candidates = vec![0];
+            for &child in &root.children {
+                candidates.push(child);
+                candidates.extend(&self.nodes[child].children);
+            }
+            found = candidates.into_iter().find(|&n| self.nodes[n].pos == *pos);
+        }
+        let Some(new_root) = found else {
+            self.nodes = vec![Node::new(pos.clone(), 0, None, None)];
+            return;
+        };
+        // copy the subtree with the new root first, remapping indices
+        let mut old = std::mem::take(&mut self.nodes);
+        let mut queue = vec![(new_root, None)];
+        while let Some((n, parent)) = queue.pop() {
+            let index = self.nodes.len();
+            let mut node =
+                std::mem::replace(&mut old[n], Node::new(Position::new(), 0, None, None));
+            node.parent = parent;
+            for &child in &node.children {
+                queue.push((child, Some(index)));
+            }
+            node.children.clear();
+            if let Some(p) = parent {
+                self.nodes[p].children.push(index);
+            }
+            self.nodes.push(node);
+        }
+    }
+
+    fn select(&self, n: usize) -> usize {
+        let parent = &self.nodes[n];
+        let log_visits = (parent.visits.max(1) as f64).ln();
+        let uct = |child: &Node| {
+            child.value / child.visits as f64
+                + self.exploration * (log_visits / child.visits as f64).sqrt()
+        };
+        *parent
+            .children
+            .iter()
+            .max_by(|&&a, &&b| uct(&self.nodes[a]).total_cmp(&uct(&self.nodes[b])))
+            .expect("a child")
+    }
+
+    fn iterate(&mut self) {
+        let mut n = 0;
+        while self.nodes[n].untried.is_empty() && !self.nodes[n].children.is_empty() {
+            n = self.select(n);
+        }
+        if !self.nodes[n].untried.is_empty() {
+            let i = self.rng.below(self.nodes[n].untried.len());
+            let col = self.nodes[n].untried.swap_remove(i);
+            let pos = &self.nodes[n].pos;
+            let next = pos.next_pos(col);
+            let terminal = if pos.is_winning_move(col) {
+                Some(1.0)
+            } else if next.moves == Position::WIDTH * Position::HEIGHT {
+                Some(0.5)
+            } else {
+                None
+            };
+            let child = self.nodes.len();
+            self.nodes.push(Node::new(next, col, Some(n), terminal));
+            self.nodes[n].children.push(child);
+            n = child;
+        }
+        let mut result = match self.nodes[n].terminal {
+            Some(result) => result,
+            None => 1.0 - self.simulate(self.nodes[n].pos.clone()),
+        };
+        let mut node = Some(n);
+        while let Some(n) = node {
+            self.nodes[n].visits += 1;
+            self.nodes[n].value += result;
+            result = 1.0 - result;
+            node = self.nodes[n].parent;
+        }
+    }
+
+    // result of a playout for the player to move in pos
+    fn simulate(&mut self, mut pos: Position) -> f64 {
+        let mut result = 1.0;
+        loop {
+            if pos.moves == Position::WIDTH * Position::HEIGHT {
+                return 0.5;
+            }
+            let col = match self.playout {
+                Playout::Random => {
+                    let cols: Vec<_> = (0..Position::WIDTH).filter(|&c| pos.can_play(c)).collect();
+                    let col = self.rng.choose(&cols).expect("a legal move");
+                    if pos.is_winning_move(col) {
+                        return result;
+                    }
+                    col
+                }
+                Playout::Heuristic => {
+                    if pos.has_winning_move() {
+                        return result;
+                    }
+                    let next = pos.possible_non_loosing_moves();
+                    let cols: Vec<_> = (0..Position::WIDTH)
+                        .filter(|&c| next & Position::column_mask(c) != 0)
+                        .collect();
+                    match self.rng.choose(&cols) {
+                        Some(col) => col,
+                        None => return 1.0 - result,
+                    }
+                }
+            };
+            pos = pos.next_pos(col);
+            result = 1.0 - result;
+        }
+    }
+
+    // runs the playouts from pos and returns the most visited column
+    pub fn search(&mut self, pos: &Position) -> Option<usize> {
+        self.reroot(pos);
+        for _ in 0..self.playouts {
+            self.iterate();
+        }
+        let root = &self.nodes[0];
+        root.children
+            .iter()
+            .map(|&child| &self.nodes[child])
+            .max_by_key(|child| child.visits)
+            .map(|child| child.col)
+    }
+}
+
+impl AiPlayer for Mcts {
+    fn name(&self) -> String {
+        let playout = match self.playout {
+            Playout::Random => "random",
+            Playout::Heuristic => "heuristic",
+        };
+        format!("mcts {} {playout} playouts", self.playouts)
+    }
+    fn choose(&mut self, pos: &Position) -> Option<usize> {
+        self.search(pos)
+    }
+}
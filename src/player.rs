@@ -0,0 +1,144 @@
+use super::*;
+use mcts::{Mcts, Playout};
+use position::OpeningBook;
+use rng::Rng;
+use solver::Solver;
+use std::sync::Arc;
+use transposition_table::OptimizedTranspoisitionTable;
+
+// anything that picks a move, so one game loop can host opponents of any strength
+pub trait AiPlayer {
+    fn name(&self) -> String;
+    // None once the position has no legal move
+    fn choose(&mut self, pos: &Position) -> Option<usize>;
+}
+
+fn playable(pos: &Position) -> Vec<usize> {
+    (0..Position::WIDTH)
+        .filter(|&col| pos.can_play(col))
+        .collect()
+}
+
+// any legal move, but always takes a win on the board
+pub struct RandomPlayer {
+    rng: Rng,
+}
+impl RandomPlayer {
+    pub fn new(rng: Rng) -> Self {
+        Self { rng }
+    }
+}
+impl AiPlayer for RandomPlayer {
+    fn name(&self) -> String {
+        "random".to_string()
+    }
+    fn choose(&mut self, pos: &Position) -> Option<usize> {
+        let cols = playable(pos);
+        match cols.iter().find(|&&col| pos.is_winning_move(col)) {
+            Some(&col) => Some(col),
+            None => self.rng.choose(&cols),
+        }
+    }
+}
+
+// Solver::analyse_depth, equal moves are picked at random
+pub struct HeuristicPlayer {
+    pub solver: Solver,
+    pub depth: usize,
+    rng: Rng,
+}
+impl HeuristicPlayer {
+    pub fn new(solver: Solver, depth: usize, rng: Rng) -> Self {
+        Self { solver, depth, rng }
+    }
+}
+impl AiPlayer for HeuristicPlayer {
+    fn name(&self) -> String {
+        format!("heuristic depth {}", self.depth)
+    }
+    fn choose(&mut self, pos: &Position) -> Option<usize> {
+        let scores = self.solver.analyse_depth(pos, self.depth);
+        let best = scores.iter().flatten().max()?;
+        let cols: Vec<_> = (0..Position::WIDTH)
+            .filter(|&col| scores[col] == Some(*best))
+            .collect();
+        self.rng.choose(&cols)
+    }
+}
+
+// exact scores from the solver turned into move probabilities,
+// temperature 0 always plays the best move, higher ones make
+// second best moves more and more likely
+pub struct SolverPlayer {
+    pub solver: Solver,
+    pub temperature: f64,
+    pub weak: bool,
+    rng: Rng,
+}
+impl SolverPlayer {
+    pub fn new(solver: Solver, temperature: f64, rng: Rng) -> Self {
+        Self {
+            solver,
+            temperature,
+            weak: false,
+            rng,
+        }
+    }
+}
+impl AiPlayer for SolverPlayer {
+    fn name(&self) -> String {
+        if self.temperature <= 0.0 {
+            "perfect".to_string()
+        } else {
+            format!("solver temperature {}", self.temperature)
+        }
+    }
+    fn choose(&mut self, pos: &Position) -> Option<usize> {
+        let scores = self.solver.analyse(pos, self.weak);
+        let best = solver::best_move(&scores)?;
+        if self.temperature <= 0.0 {
+            return Some(best);
+        }
+        // softmax, shifted by the best score so exp never overflows
+        let top = scores[best]? as f64;
+        let weights: Vec<_> = scores
+            .iter()
+            .map(|s| s.map_or(0.0, |s| ((s as f64 - top) / self.temperature).exp()))
+            .collect();
+        let mut x = self.rng.next_f64() * weights.iter().sum::<f64>();
+        for (col, w) in weights.iter().enumerate() {
+            if x < *w {
+                return Some(col);
+            }
+            x -= w;
+        }
+        Some(best)
+    }
+}
+
+// preset strengths for casual games
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+    Random,
+    Easy,
+    Medium,
+    Hard,
+    Perfect,
+    // monte carlo tree search with this many heuristic playouts per move
+    Mcts(usize),
+}
+impl Level {
+    pub fn player(self, book: Arc<OpeningBook>, table_size: usize, rng: Rng) -> Box<dyn AiPlayer> {
+        let solver = |size| Solver::with_table_size(book, size);
+        let min_size = OptimizedTranspoisitionTable::MIN_SIZE;
+        match self {
+            Self::Random => Box::new(RandomPlayer::new(rng)),
+            // the depth limited search never touches the table, keep it small
+            Self::Easy => Box::new(HeuristicPlayer::new(solver(min_size), 2, rng)),
+            Self::Medium => Box::new(HeuristicPlayer::new(solver(min_size), 6, rng)),
+            Self::Hard => Box::new(SolverPlayer::new(solver(table_size), 1.0, rng)),
+            Self::Perfect => Box::new(SolverPlayer::new(solver(table_size), 0.0, rng)),
+            Self::Mcts(playouts) => Box::new(Mcts::new(playouts, Playout::Heuristic, rng)),
+        }
+    }
+}
@@ -1,8 +1,7 @@
-use std::borrow::BorrowMut;
+use std::collections::HashSet;
 use std::fmt;
 use std::io::Read;
-use std::ops::RangeBounds;
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Position {
     pub moves: usize,
     current_position: u64,
@@ -10,15 +9,73 @@ pub struct Position {
 }
 impl fmt::Display for Position {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "player: {}\nboard:\n{}",
-            self.get_current_player(),
-            self.mask
-        )
+        writeln!(f, "player: {}\nboard:", self.get_current_player())?;
+        for row in (0..Self::HEIGHT).rev() {
+            let cells: Vec<_> = (0..Self::WIDTH)
+                .map(|col| match self.cell(col, row) {
+                    Some(1) => "X",
+                    Some(_) => "O",
+                    None => ".",
+                })
+                .collect();
+            writeln!(f, "{}", cells.join(" "))?;
+        }
+        let cols: Vec<_> = (1..=Self::WIDTH).map(|c| c.to_string()).collect();
+        write!(f, "{}", cols.join(" "))
+    }
+}
+
+// positions travel as their Notation::Key string, finished games can't
+// be read back so they don't serialize either
+#[cfg(feature = "serde")]
+impl serde::Serialize for Position {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let key = self.format(Notation::Key).ok_or_else(|| {
+            serde::ser::Error::custom(format!("game over in position {:?}", self.key64()))
+        })?;
+        serializer.serialize_str(&key)
+    }
+}
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Position {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let code = String::deserialize(deserializer)?;
+        Self::parse_notation(&code, Notation::Key)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid position key {code:?}")))
     }
 }
 
+// ways of writing down a position
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Notation {
+    // moves as columns 1-7, like Position::parse and the datasets
+    OneBased,
+    // moves as columns 0-6, like Position::parse_safe
+    ZeroBased,
+    // moves as columns a-g, lowercase only so keys rarely read as moves
+    Letters,
+    // an input line of Pascal Pons' reference solver, 1-based moves
+    // optionally followed by the score as in the Test_L*_R* files
+    Reference,
+    // key() in url safe base64, 9 characters
+    Key,
+}
+impl Notation {
+    pub const ALL: [Self; 5] = [
+        Self::OneBased,
+        Self::ZeroBased,
+        Self::Letters,
+        Self::Reference,
+        Self::Key,
+    ];
+}
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+impl Default for Position {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl Position {
     pub const HEIGHT: usize = 6;
     pub const WIDTH: usize = 7;
@@ -32,7 +89,7 @@ impl Position {
             mask: 0,
         }
     }
-    // first column == 1
+    // first column == 1, see parse_notation for checked parsing
     pub fn parse(code: &str) -> Self {
         code.chars()
             .flat_map(|c| c.to_digit(10).map(|d| d - 1))
@@ -44,9 +101,196 @@ impl Position {
             c.to_digit(10).and_then(|d| acc.next_pos_safe(d as usize))
         })
     }
+    // first column == 1, None on invalid characters, full columns
+    // or moves once the game is decided
+    pub fn try_parse(code: &str) -> Option<Self> {
+        code.chars().try_fold(Self::new(), |acc, c| {
+            let col = c.to_digit(10).filter(|d| *d > 0)? as usize - 1;
+            if col < Self::WIDTH && acc.can_play(col) && !acc.is_winning_move(col) {
+                Some(acc.next_pos(col))
+            } else {
+                None
+            }
+        })
+    }
+    // plays 0-based columns under the rules of try_parse
+    fn play_all(cols: impl IntoIterator<Item = Option<usize>>) -> Option<Self> {
+        cols.into_iter().try_fold(Self::new(), |acc, col| {
+            let col = col.filter(|&col| col < Self::WIDTH)?;
+            if acc.can_play(col) && !acc.is_winning_move(col) {
+                Some(acc.next_pos(col))
+            } else {
+                None
+            }
+        })
+    }
+    pub fn parse_notation(code: &str, notation: Notation) -> Option<Self> {
+        let digits = |offset: u32| {
+            let cols = code.chars().map(move |c| {
+                let d = c.to_digit(10)?.checked_sub(offset)?;
+                Some(d as usize)
+            });
+            Self::play_all(cols)
+        };
+        match notation {
+            Notation::OneBased => digits(1),
+            Notation::ZeroBased => digits(0),
+            Notation::Letters => Self::play_all(
+                code.chars()
+                    .map(|c| c.is_ascii_lowercase().then(|| (c as u8 - b'a') as usize)),
+            ),
+            Notation::Reference => {
+                let mut fields = code.split_whitespace();
+                let pos = Self::parse_notation(fields.next().unwrap_or(""), Notation::OneBased)?;
+                match (fields.next().map(str::parse::<isize>), fields.next()) {
+                    (None | Some(Ok(_)), None) => Some(pos),
+                    _ => None,
+                }
+            }
+            Notation::Key => Self::from_key64(code),
+        }
+    }
+    // the position in any notation that reads it, None when notations
+    // disagree like 1-based and 0-based digits mostly do
+    pub fn parse_any(code: &str) -> Option<Self> {
+        let mut parsed = Notation::ALL
+            .iter()
+            .flat_map(|&n| Self::parse_notation(code, n));
+        let pos = parsed.next()?;
+        parsed.all(|p| p == pos).then_some(pos)
+    }
+    // None in every notation when no sequence of legal moves reaches the
+    // position or the game is over, parse_notation doesn't read those back
+    pub fn format(&self, notation: Notation) -> Option<String> {
+        if self.is_over() {
+            return None;
+        }
+        let cols = self.move_order()?.into_iter();
+        Some(match notation {
+            Notation::OneBased | Notation::Reference => cols.map(|c| (c + 1).to_string()).collect(),
+            Notation::ZeroBased => cols.map(|c| c.to_string()).collect(),
+            Notation::Letters => cols.map(|c| (b'a' + c as u8) as char).collect(),
+            Notation::Key => self.key64(),
+        })
+    }
+    // some order of moves leading to the position, found by taking back
+    // top pieces of alternate players as long as no earlier board has a four
+    pub fn move_order(&self) -> Option<Vec<usize>> {
+        fn take_back(pos: &Position, failed: &mut HashSet<u64>, cols: &mut Vec<usize>) -> bool {
+            if pos.moves == 0 {
+                return true;
+            }
+            if failed.contains(&pos.key()) {
+                return false;
+            }
+            let last_player = pos.current_position ^ pos.mask;
+            for col in 0..Position::WIDTH {
+                let top = (pos.mask & Position::column_mask(col)) + Position::bottom_mask(col);
+                let top = (top >> 1) & Position::column_mask(col);
+                if top & last_player == 0 {
+                    continue;
+                }
+                let prev = Position {
+                    moves: pos.moves - 1,
+                    current_position: last_player ^ top,
+                    mask: pos.mask ^ top,
+                };
+                if Position::has_four(prev.current_position)
+                    || Position::has_four(pos.current_position)
+                {
+                    continue;
+                }
+                cols.push(col);
+                if take_back(&prev, failed, cols) {
+                    return true;
+                }
+                cols.pop();
+            }
+            failed.insert(pos.key());
+            false
+        }
+        let mut cols = Vec::new();
+        take_back(self, &mut HashSet::new(), &mut cols).then(|| {
+            cols.reverse();
+            cols
+        })
+    }
+    // key() in 9 url safe base64 characters
+    pub fn key64(&self) -> String {
+        (0..9)
+            .rev()
+            .map(|i| BASE64[(self.key() >> (6 * i)) as usize & 63] as char)
+            .collect()
+    }
+    // each column of key() holds 2^height - 1 plus the pieces of the
+    // player to move, so the heights and pieces can be read back
+    fn from_key64(code: &str) -> Option<Self> {
+        if code.len() != 9 {
+            return None;
+        }
+        let key = code.bytes().try_fold(0_u64, |key, b| {
+            let digit = BASE64.iter().position(|&c| c == b)?;
+            Some(key << 6 | digit as u64)
+        })?;
+        let (mut current_position, mut mask) = (0, 0);
+        for col in 0..Self::WIDTH {
+            let shift = col * (Self::HEIGHT + 1);
+            let v = (key >> shift) & ((1 << (Self::HEIGHT + 1)) - 1);
+            let height = 63 - (v + 1).leading_zeros() as usize;
+            if height > Self::HEIGHT {
+                return None;
+            }
+            let column = (1 << height) - 1;
+            mask |= column << shift;
+            current_position |= (v - column) << shift;
+        }
+        let pos = Self {
+            moves: Self::pop_count(mask, 0),
+            current_position,
+            mask,
+        };
+        let valid = key >> (Self::WIDTH * (Self::HEIGHT + 1)) == 0
+            && Self::pop_count(current_position, 0) == pos.moves / 2
+            && !Self::has_four(current_position)
+            && !Self::has_four(current_position ^ mask);
+        // pieces of the right counts can still be stacked in an order no
+        // game plays them
+        (valid && pos.move_order().is_some()).then_some(pos)
+    }
+    // the last move made a four
+    fn is_over(&self) -> bool {
+        Self::has_four(self.current_position ^ self.mask)
+    }
+    fn has_four(position: u64) -> bool {
+        [1, Self::HEIGHT, Self::HEIGHT + 1, Self::HEIGHT + 2]
+            .into_iter()
+            .any(|shift| {
+                let m = position & (position >> shift);
+                m & (m >> (2 * shift)) != 0
+            })
+    }
     pub fn key(&self) -> u64 {
         self.current_position + self.mask
     }
+    // same for a position and its mirror image, exact on any position
+    // unlike key3
+    pub fn symmetric_key(&self) -> u64 {
+        std::cmp::min(self.key(), self.mirror().key())
+    }
+    // the position with the columns in reverse order
+    pub fn mirror(&self) -> Self {
+        let flip = |bits: u64| {
+            (0..Self::WIDTH).fold(0, |acc, col| {
+                let column = (bits >> (col * (Self::HEIGHT + 1))) & ((1 << (Self::HEIGHT + 1)) - 1);
+                acc | column << ((Self::WIDTH - 1 - col) * (Self::HEIGHT + 1))
+            })
+        };
+        Self {
+            moves: self.moves,
+            current_position: flip(self.current_position),
+            mask: flip(self.mask),
+        }
+    }
     pub fn key3(&self) -> u64 {
         let mut key_forward = 0;
         for i in 0..Self::WIDTH {
@@ -78,7 +322,7 @@ impl Position {
         let forced_moves = possible_mask & opponent_win;
 
         match forced_moves {
-            n if n == 0 => possible_mask & !(opponent_win >> 1),
+            0 => possible_mask & !(opponent_win >> 1),
             n if n & (n - 1) > 0 => 0,
             _ => forced_moves & !(opponent_win >> 1),
         }
@@ -89,13 +333,13 @@ impl Position {
     }
 
     pub fn next_pos(&self, col: usize) -> Self {
-        let m = self.mask + Self::bottom_mask(col) & Self::column_mask(col);
+        let m = (self.mask + Self::bottom_mask(col)) & Self::column_mask(col);
         self.next_pos_move(m)
     }
 
     pub fn next_pos_safe(&self, col: usize) -> Option<Self> {
-        if self.can_play(col) && col < Self::WIDTH {
-            let m = self.mask + Self::bottom_mask(col) & Self::column_mask(col);
+        if col < Self::WIDTH && self.can_play(col) {
+            let m = (self.mask + Self::bottom_mask(col)) & Self::column_mask(col);
             Some(self.next_pos_move(m))
         } else {
             None
@@ -115,6 +359,21 @@ impl Position {
     fn get_current_player(&self) -> usize {
         1 + self.moves % 2
     }
+    // player to move, 1 started the game
+    pub fn current_player(&self) -> usize {
+        self.get_current_player()
+    }
+    // player (1 or 2) owning the cell, row 0 is the bottom
+    pub fn cell(&self, col: usize, row: usize) -> Option<usize> {
+        let p = 1_u64 << (row + col * (Self::HEIGHT + 1));
+        if p & self.mask == 0 {
+            None
+        } else if p & self.current_position != 0 {
+            Some(self.get_current_player())
+        } else {
+            Some(3 - self.get_current_player())
+        }
+    }
 
     pub fn has_winning_move(&self) -> bool {
         (self.winning_position() & self.possible()) != 0
@@ -138,6 +397,39 @@ impl Position {
     fn opponent_winning_position(&self) -> u64 {
         Self::compute_winning_position(self.current_position ^ self.mask, self.mask)
     }
+    // empty cells completing a four for the player to move and the opponent
+    pub fn threats(&self) -> (usize, usize) {
+        (
+            Self::pop_count(self.winning_position(), 0),
+            Self::pop_count(self.opponent_winning_position(), 0),
+        )
+    }
+    // static guess for the player to move, positive is good, always well
+    // inside +-EVAL_BOUND. Threats count most, more so on rows of the
+    // right parity: the first player wins zugzwang fights with threats on
+    // odd rows (counted from 1 at the bottom), the second player with
+    // threats on even rows. Pieces nearer the centre join more fours.
+    pub fn evaluate(&self) -> isize {
+        let own = self.winning_position();
+        let opponent = self.opponent_winning_position();
+        let (own_rows, opponent_rows) = match self.get_current_player() {
+            1 => (Self::ODD_ROWS_MASK, !Self::ODD_ROWS_MASK),
+            _ => (!Self::ODD_ROWS_MASK, Self::ODD_ROWS_MASK),
+        };
+        let threats = |m: u64, rows: u64| {
+            4 * Self::pop_count(m, 0) as isize + 4 * Self::pop_count(m & rows, 0) as isize
+        };
+        let centre = |position: u64| {
+            (0..Self::WIDTH)
+                .map(|col| {
+                    let weight = (Self::WIDTH / 2 - col.abs_diff(Self::WIDTH / 2)) as isize;
+                    weight * Self::pop_count(position & Self::column_mask(col), 0) as isize
+                })
+                .sum::<isize>()
+        };
+        threats(own, own_rows) - threats(opponent, opponent_rows) + centre(self.current_position)
+            - centre(self.current_position ^ self.mask)
+    }
     pub fn move_score(&self, m: u64) -> usize {
         Self::pop_count(
             Self::compute_winning_position(self.current_position | m, self.mask),
@@ -156,52 +448,56 @@ impl Position {
         let mut r = (position << 1) & (position << 2) & (position << 3);
 
         // horizontal
-        let mut p = (position << (Self::HEIGHT + 1)) & (position << 2 * (Self::HEIGHT + 1));
-        r |= p & (position << 3 * (Self::HEIGHT + 1));
+        let mut p = (position << (Self::HEIGHT + 1)) & (position << (2 * (Self::HEIGHT + 1)));
+        r |= p & (position << (3 * (Self::HEIGHT + 1)));
         r |= p & (position >> (Self::HEIGHT + 1));
-        p = (position >> (Self::HEIGHT + 1)) & (position >> 2 * (Self::HEIGHT + 1));
+        p = (position >> (Self::HEIGHT + 1)) & (position >> (2 * (Self::HEIGHT + 1)));
         r |= p & (position << (Self::HEIGHT + 1));
-        r |= p & (position >> 3 * (Self::HEIGHT + 1));
+        r |= p & (position >> (3 * (Self::HEIGHT + 1)));
 
         //diagonal 1
-        p = (position << Self::HEIGHT) & (position << 2 * Self::HEIGHT);
-        r |= p & (position << 3 * Self::HEIGHT);
+        p = (position << Self::HEIGHT) & (position << (2 * Self::HEIGHT));
+        r |= p & (position << (3 * Self::HEIGHT));
         r |= p & (position >> Self::HEIGHT);
-        p = (position >> Self::HEIGHT) & (position >> 2 * Self::HEIGHT);
+        p = (position >> Self::HEIGHT) & (position >> (2 * Self::HEIGHT));
         r |= p & (position << Self::HEIGHT);
-        r |= p & (position >> 3 * Self::HEIGHT);
+        r |= p & (position >> (3 * Self::HEIGHT));
 
         //diagonal 2
-        p = (position << (Self::HEIGHT + 2)) & (position << 2 * (Self::HEIGHT + 2));
-        r |= p & (position << 3 * (Self::HEIGHT + 2));
+        p = (position << (Self::HEIGHT + 2)) & (position << (2 * (Self::HEIGHT + 2)));
+        r |= p & (position << (3 * (Self::HEIGHT + 2)));
         r |= p & (position >> (Self::HEIGHT + 2));
-        p = (position >> (Self::HEIGHT + 2)) & (position >> 2 * (Self::HEIGHT + 2));
+        p = (position >> (Self::HEIGHT + 2)) & (position >> (2 * (Self::HEIGHT + 2)));
         r |= p & (position << (Self::HEIGHT + 2));
-        r |= p & (position >> 3 * (Self::HEIGHT + 2));
+        r |= p & (position >> (3 * (Self::HEIGHT + 2)));
 
         r & (Self::BOARD_MASK ^ mask)
     }
     // static bitmaps
-    const BOTTOM_MASK: u64 = 4432676798593; //Self::bottom(Self::WIDTH as u64, Self::HEIGHT as u64);
+    const BOTTOM_MASK: u64 = Self::bottom(Self::WIDTH as u64, Self::HEIGHT as u64);
     const BOARD_MASK: u64 = Self::BOTTOM_MASK * ((1_u64 << Self::HEIGHT) - 1);
+    // rows 1, 3 and 5 counted from 1 at the bottom
+    const ODD_ROWS_MASK: u64 = Self::BOTTOM_MASK * 0b10101;
+    // evaluate never reaches it, depth limited scores beyond it are proven
+    pub const EVAL_BOUND: isize = 1000;
 
     // mask functions
     fn top_mask(col: usize) -> u64 {
         1_u64 << ((Self::HEIGHT - 1) + col * (Self::HEIGHT + 1))
     }
     fn bottom_mask(col: usize) -> u64 {
-        1_u64 << col * (Self::HEIGHT + 1)
+        1_u64 << (col * (Self::HEIGHT + 1))
     }
 
     pub fn column_mask(col: usize) -> u64 {
-        ((1_u64 << Self::HEIGHT) - 1) << col * (Self::HEIGHT + 1)
+        ((1_u64 << Self::HEIGHT) - 1) << (col * (Self::HEIGHT + 1))
     }
 
-    // broken function /*TODO*/
-    const fn bottom(height: u64, width: u64) -> u64 {
+    // one bit at the bottom of every column
+    const fn bottom(width: u64, height: u64) -> u64 {
         match width {
             0 => 0,
-            _ => Self::bottom(width - 1, height) | 1_u64 << (width - 1) * (height + 1),
+            _ => Self::bottom(width - 1, height) | (1_u64 << ((width - 1) * (height + 1))),
         }
     }
 }
@@ -211,6 +507,11 @@ pub struct MoveSorter {
     entries: [SortEntry; Position::WIDTH],
 }
 
+impl Default for MoveSorter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl MoveSorter {
     pub fn new() -> Self {
         Self {
@@ -260,37 +561,247 @@ impl SortEntry {
     }
 }
 
-use crate::transposition_table::BookTranspositionTable;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::transposition_table::MappedBookTable;
+use crate::transposition_table::{BookTable, BookTranspositionTable};
 use std::error::Error;
 use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+#[derive(Debug)]
+pub enum BookError {
+    Io(io::Error),
+    Truncated,
+    TrailingData,
+    BoardSize { width: u8, height: u8 },
+    Depth(u8),
+    KeySize(u8),
+    ValueSize(u8),
+    LogSize(u8),
+    // score outside Position::MIN_SCORE..=Position::MAX_SCORE
+    Score(isize),
+}
+impl fmt::Display for BookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "io error reading book: {e}"),
+            Self::Truncated => write!(f, "book file is truncated"),
+            Self::TrailingData => write!(f, "book file has trailing data"),
+            Self::BoardSize { width, height } => write!(
+                f,
+                "book is for a {width}x{height} board, expected {}x{}",
+                Position::WIDTH,
+                Position::HEIGHT
+            ),
+            Self::Depth(d) => write!(
+                f,
+                "book depth {d} exceeds {}, deeper keys don't fit the book table",
+                OpeningBook::MAX_DEPTH
+            ),
+            Self::KeySize(n) => write!(f, "unsupported key size {n}, expected 1"),
+            Self::ValueSize(n) => write!(f, "unsupported value size {n}, expected 1"),
+            Self::LogSize(n) => write!(
+                f,
+                "unsupported log size {n}, expected {}",
+                BookTranspositionTable::LOG_SIZE
+            ),
+            Self::Score(n) => write!(
+                f,
+                "score {n} outside {}..={}",
+                Position::MIN_SCORE,
+                Position::MAX_SCORE
+            ),
+        }
+    }
+}
+impl Error for BookError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+impl From<io::Error> for BookError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::UnexpectedEof => Self::Truncated,
+            _ => Self::Io(e),
+        }
+    }
+}
+
+// width, height, depth, key size, value size, log2 of the table size
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BookHeader {
+    pub width: u8,
+    pub height: u8,
+    pub depth: u8,
+    pub key_size: u8,
+    pub value_size: u8,
+    pub log_size: u8,
+}
+impl BookHeader {
+    pub const LEN: usize = 6;
+
+    pub fn new(depth: usize) -> Self {
+        Self {
+            width: Position::WIDTH as u8,
+            height: Position::HEIGHT as u8,
+            depth: depth as u8,
+            key_size: 1,
+            value_size: 1,
+            log_size: BookTranspositionTable::LOG_SIZE,
+        }
+    }
+    pub fn to_bytes(self) -> [u8; Self::LEN] {
+        [
+            self.width,
+            self.height,
+            self.depth,
+            self.key_size,
+            self.value_size,
+            self.log_size,
+        ]
+    }
+
+    pub fn parse(bytes: [u8; Self::LEN]) -> Result<Self, BookError> {
+        let [width, height, depth, key_size, value_size, log_size] = bytes;
+        let header = Self {
+            width,
+            height,
+            depth,
+            key_size,
+            value_size,
+            log_size,
+        };
+        header.validate()?;
+        Ok(header)
+    }
+    pub fn validate(&self) -> Result<(), BookError> {
+        if self.width as usize != Position::WIDTH || self.height as usize != Position::HEIGHT {
+            Err(BookError::BoardSize {
+                width: self.width,
+                height: self.height,
+            })
+        } else if self.depth as usize > OpeningBook::MAX_DEPTH {
+            Err(BookError::Depth(self.depth))
+        } else if self.key_size != 1 {
+            Err(BookError::KeySize(self.key_size))
+        } else if self.value_size != 1 {
+            Err(BookError::ValueSize(self.value_size))
+        } else if self.log_size != BookTranspositionTable::LOG_SIZE {
+            Err(BookError::LogSize(self.log_size))
+        } else {
+            Ok(())
+        }
+    }
+}
+
 pub struct OpeningBook {
-    pub table: BookTranspositionTable,
+    pub table: Box<dyn BookTable>,
     depth: usize,
 }
+impl PartialEq for OpeningBook {
+    fn eq(&self, other: &Self) -> bool {
+        self.depth == other.depth
+            && self.table.keys() == other.table.keys()
+            && self.table.values() == other.table.values()
+    }
+}
+impl Eq for OpeningBook {}
+impl Default for OpeningBook {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl OpeningBook {
+    // key3 stays below the 1 << 44 the book table takes up to this many
+    // moves, 3^(21 + 6) < 2^44
+    pub const MAX_DEPTH: usize = 21;
     pub fn new() -> Self {
         Self {
-            table: BookTranspositionTable::new(),
+            table: Box::new(BookTranspositionTable::new()),
             depth: 0,
         }
     }
-    pub fn load(file_path: &str) -> Result<Self, Box<dyn Error>> {
-        let mut file: File = File::open(file_path)?;
-        let depth = file
-            .borrow_mut()
-            .take(6)
-            .bytes()
-            .flatten()
-            .collect::<Vec<u8>>()[2];
+    // bytes in a book file
+    const FILE_LEN: u64 = (BookHeader::LEN + 2 * BookTranspositionTable::SIZE) as u64;
+    fn check_len(len: u64) -> Result<(), BookError> {
+        match len {
+            n if n < Self::FILE_LEN => Err(BookError::Truncated),
+            n if n > Self::FILE_LEN => Err(BookError::TrailingData),
+            _ => Ok(()),
+        }
+    }
+    pub fn load(file_path: &str) -> Result<Self, BookError> {
+        let file = File::open(file_path)?;
+        Self::check_len(file.metadata()?.len())?;
+        Self::from_reader(file)
+    }
+    // book file contents from anywhere, a decompressing stream or a socket.
+    // Reads exactly the length of a book and leaves whatever follows it
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Self, BookError> {
+        let mut header = [0; BookHeader::LEN];
+        reader.read_exact(&mut header)?;
+        let header = BookHeader::parse(header)?;
+
         let mut keys = vec![0; BookTranspositionTable::SIZE];
-        file.read_exact(&mut keys)?;
+        reader.read_exact(&mut keys)?;
         let mut values = vec![0; BookTranspositionTable::SIZE];
-        file.read_exact(&mut values)?;
+        reader.read_exact(&mut values)?;
+        Ok(Self {
+            table: Box::new(BookTranspositionTable::create(keys, values)),
+            depth: header.depth as usize,
+        })
+    }
+    // a whole book file already in memory, like include_bytes!("7x6.book")
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BookError> {
+        Self::check_len(bytes.len() as u64)?;
+        Self::from_reader(bytes)
+    }
+    // same as load, but pages the tables in from disk on first access
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_mapped(file_path: &str) -> Result<Self, BookError> {
+        let mut file: File = File::open(file_path)?;
+        let mut header = [0; BookHeader::LEN];
+        file.read_exact(&mut header)?;
+        let header = BookHeader::parse(header)?;
+
+        Self::check_len(file.metadata()?.len())?;
         Ok(Self {
-            table: BookTranspositionTable::create(keys, values),
-            depth: depth as usize,
+            table: Box::new(MappedBookTable::map(&file, BookHeader::LEN)?),
+            depth: header.depth as usize,
         })
     }
+    pub fn save(&self, file_path: &str) -> Result<(), BookError> {
+        self.write_to(BufWriter::new(File::create(file_path)?))
+    }
+    // what save writes to the file, readable with from_reader
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), BookError> {
+        writer.write_all(&BookHeader::new(self.depth).to_bytes())?;
+        writer.write_all(self.table.keys())?;
+        writer.write_all(self.table.values())?;
+        writer.flush()?;
+        Ok(())
+    }
+    // score as returned by the solver, positions deeper than the book grow
+    // it up to MAX_DEPTH
+    pub fn insert(&mut self, pos: &Position, score: isize) -> Result<(), BookError> {
+        if !(Position::MIN_SCORE..=Position::MAX_SCORE).contains(&score) {
+            return Err(BookError::Score(score));
+        }
+        if pos.moves > Self::MAX_DEPTH {
+            return Err(BookError::Depth(pos.moves as u8));
+        }
+        self.table
+            .put(pos.key3(), (score - Position::MIN_SCORE + 1) as u8);
+        self.depth = std::cmp::max(self.depth, pos.moves);
+        Ok(())
+    }
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
     pub fn get(&self, pos: &Position) -> Option<u8> {
         if pos.moves > self.depth {
             None
@@ -301,4 +812,40 @@ impl OpeningBook {
             }
         }
     }
+    pub fn score(&self, pos: &Position) -> Option<isize> {
+        self.get(pos).map(|n| n as isize + Position::MIN_SCORE - 1)
+    }
+    // scores of every column from the point of view of the player to move,
+    // None if a legal move leads outside the book
+    pub fn analyse(&self, pos: &Position) -> Option<Vec<Option<isize>>> {
+        (0..Position::WIDTH)
+            .map(|col| {
+                if !pos.can_play(col) {
+                    Some(None)
+                } else if pos.is_winning_move(col) {
+                    Some(Some(
+                        ((Position::WIDTH * Position::HEIGHT + 1 - pos.moves) / 2) as isize,
+                    ))
+                } else {
+                    self.score(&pos.next_pos(col)).map(|score| Some(-score))
+                }
+            })
+            .collect()
+    }
+    pub fn best_move(&self, pos: &Position) -> Option<usize> {
+        crate::solver::best_move(&self.analyse(pos)?)
+    }
+    // book-only play: best moves from pos until the book runs out
+    pub fn line(&self, pos: &Position) -> Vec<usize> {
+        let mut pos = pos.clone();
+        let mut line = Vec::new();
+        while let Some(col) = self.best_move(&pos) {
+            line.push(col);
+            if pos.is_winning_move(col) {
+                break;
+            }
+            pos = pos.next_pos(col);
+        }
+        line
+    }
 }
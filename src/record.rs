@@ -0,0 +1,301 @@
+use super::*;
+use game::{Game, Status};
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// pgn-like game records, any number per file:
+//
+//   [Player1 "perfect"]
+//   [Player2 "mcts 1000 heuristic playouts"]
+//   [Date "2024.05.01"]
+//   [Width "7"]
+//   [Height "6"]
+//   [Result "1-0"]
+//
+//   1. 4 {[%eval 1] the only winning move} 4 2. 4 4 ... 1-0
+//
+// moves are 1-based columns, move numbers are optional when reading and
+// a result or a tag after moves ends the game.
+// An annotation in braces belongs to the move before it and holds an
+// optional evaluation and a comment, it has to end on the same line.
+
+#[derive(Debug)]
+pub enum RecordError {
+    Io(io::Error),
+    // 1-based line number and what is wrong with it
+    Parse(usize, String),
+    BoardSize { width: usize, height: usize },
+    // 0-based index of the first move that can't be played
+    IllegalMove(usize),
+}
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "couldn't read game records: {e}"),
+            Self::Parse(n, msg) => write!(f, "line {n}: {msg}"),
+            Self::BoardSize { width, height } => write!(
+                f,
+                "game on a {width}x{height} board, expected {}x{}",
+                Position::WIDTH,
+                Position::HEIGHT
+            ),
+            Self::IllegalMove(i) => write!(f, "move {} can't be played", i + 1),
+        }
+    }
+}
+impl Error for RecordError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+impl From<io::Error> for RecordError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+pub fn result_tag(status: Status) -> &'static str {
+    match status {
+        Status::Won(1) => "1-0",
+        Status::Won(_) => "0-1",
+        Status::Draw => "1/2-1/2",
+        Status::Ongoing => "*",
+    }
+}
+
+// "yyyy.mm.dd" in UTC as the Date tag holds it
+pub fn date_tag(time: SystemTime) -> String {
+    let days = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() / 86400) as i64;
+    // civil from days, eras of 400 years starting on 0000-03-01
+    let z = days + 719468;
+    let (era, doe) = (z.div_euclid(146097), z.rem_euclid(146097));
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!("{year:04}.{month:02}.{day:02}")
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordMove {
+    // 0-based column
+    pub col: usize,
+    // score for the player making the move
+    pub eval: Option<isize>,
+    pub comment: Option<String>,
+}
+impl RecordMove {
+    pub fn new(col: usize) -> Self {
+        Self {
+            col,
+            eval: None,
+            comment: None,
+        }
+    }
+    fn annotate(&mut self, annotation: &str) -> Option<()> {
+        let mut rest = annotation.trim();
+        if let Some(eval) = rest.strip_prefix("[%eval ") {
+            let (score, comment) = eval.split_once(']')?;
+            self.eval = Some(score.trim().parse().ok()?);
+            rest = comment.trim();
+        }
+        self.comment = (!rest.is_empty()).then(|| rest.to_string());
+        Some(())
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GameRecord {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<RecordMove>,
+}
+impl GameRecord {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    // moves of game with today's date, its board size and result
+    pub fn from_game(game: &Game) -> Self {
+        let mut record = Self::new();
+        record.set_tag("Date", &date_tag(SystemTime::now()));
+        record.set_tag("Width", &Position::WIDTH.to_string());
+        record.set_tag("Height", &Position::HEIGHT.to_string());
+        record.set_tag("Result", result_tag(game.status()));
+        record.moves = game
+            .history()
+            .iter()
+            .map(|&col| RecordMove::new(col))
+            .collect();
+        record
+    }
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+    pub fn set_tag(&mut self, key: &str, value: &str) {
+        match self.tags.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value.to_string(),
+            None => self.tags.push((key.to_string(), value.to_string())),
+        }
+    }
+    // replays the moves, checking the board size tags first
+    pub fn game(&self) -> Result<Game, RecordError> {
+        // unreadable sizes count as 0
+        let size = |key, default| self.tag(key).map_or(default, |v| v.parse().unwrap_or(0));
+        let (width, height) = (
+            size("Width", Position::WIDTH),
+            size("Height", Position::HEIGHT),
+        );
+        if (width, height) != (Position::WIDTH, Position::HEIGHT) {
+            return Err(RecordError::BoardSize { width, height });
+        }
+        let mut game = Game::new();
+        for (i, m) in self.moves.iter().enumerate() {
+            if !game.play(m.col) {
+                return Err(RecordError::IllegalMove(i));
+            }
+        }
+        Ok(game)
+    }
+    pub fn position(&self) -> Result<Position, RecordError> {
+        Ok(self.game()?.position())
+    }
+
+    pub fn parse(s: &str) -> Result<Vec<Self>, RecordError> {
+        let mut records = Vec::new();
+        let mut record = Self::new();
+        let mut in_moves = false;
+        for (i, line) in s.lines().enumerate() {
+            let n = i + 1;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with('[') {
+                // tags after movetext start the next game
+                if in_moves {
+                    records.push(std::mem::take(&mut record));
+                    in_moves = false;
+                }
+                let (key, value) = parse_tag(line)
+                    .ok_or_else(|| RecordError::Parse(n, format!("invalid tag {line:?}")))?;
+                record.set_tag(&key, &value);
+            } else {
+                in_moves = true;
+                // a result ends the game, more moves start the next one
+                let mut rest = line;
+                while let Some(after) = record.parse_moves(n, rest)? {
+                    records.push(std::mem::take(&mut record));
+                    in_moves = false;
+                    rest = after;
+                }
+            }
+        }
+        if in_moves || !record.tags.is_empty() {
+            records.push(record);
+        }
+        Ok(records)
+    }
+    pub fn load(file_path: &str) -> Result<Vec<Self>, RecordError> {
+        Self::parse(&std::fs::read_to_string(file_path)?)
+    }
+    pub fn save(file_path: &str, records: &[Self]) -> Result<(), RecordError> {
+        let text: Vec<_> = records.iter().map(|r| r.to_string()).collect();
+        std::fs::write(file_path, text.join("\n"))?;
+        Ok(())
+    }
+
+    // moves of line up to its end, or up to a result and Some of the rest
+    fn parse_moves<'a>(&mut self, n: usize, line: &'a str) -> Result<Option<&'a str>, RecordError> {
+        let err = |msg: String| RecordError::Parse(n, msg);
+        let mut rest = line;
+        loop {
+            rest = rest.trim_start();
+            if rest.is_empty() {
+                return Ok(None);
+            }
+            if let Some(annotated) = rest.strip_prefix('{') {
+                let (annotation, after) = annotated
+                    .split_once('}')
+                    .ok_or_else(|| err("unclosed annotation".to_string()))?;
+                let invalid = || err(format!("invalid annotation {{{annotation}}}"));
+                let last = self.moves.last_mut().ok_or_else(invalid)?;
+                last.annotate(annotation).ok_or_else(invalid)?;
+                rest = after;
+                continue;
+            }
+            let end = rest
+                .find(|c: char| c.is_whitespace() || c == '{')
+                .unwrap_or(rest.len());
+            let (token, after) = rest.split_at(end);
+            rest = after;
+            match token {
+                "1-0" | "0-1" | "1/2-1/2" | "*" => {
+                    if self.tag("Result").is_none() {
+                        self.set_tag("Result", token);
+                    }
+                    return Ok(Some(rest));
+                }
+                t if t.ends_with('.') && t[..t.len() - 1].parse::<usize>().is_ok() => {}
+                t => match t.parse::<usize>() {
+                    Ok(col) if (1..=Position::WIDTH).contains(&col) => {
+                        self.moves.push(RecordMove::new(col - 1))
+                    }
+                    _ => return Err(err(format!("invalid move {t:?}"))),
+                },
+            }
+        }
+    }
+}
+
+// [Key "value"] with \" and \\ escapes in the value
+fn parse_tag(line: &str) -> Option<(String, String)> {
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?;
+    let (key, value) = inner.split_once(char::is_whitespace)?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+    let mut unescaped = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.push(chars.next()?),
+            c => unescaped.push(c),
+        }
+    }
+    Some((key.to_string(), unescaped))
+}
+
+impl fmt::Display for GameRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (key, value) in &self.tags {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            writeln!(f, "[{key} \"{value}\"]")?;
+        }
+        writeln!(f)?;
+        for (i, m) in self.moves.iter().enumerate() {
+            if i % 2 == 0 {
+                write!(f, "{}. ", i / 2 + 1)?;
+            }
+            write!(f, "{} ", m.col + 1)?;
+            // keep the annotation readable as one
+            let comment = m.comment.as_ref().map(|c| c.replace(['{', '}', '\n'], " "));
+            let annotation = match (m.eval, comment) {
+                (Some(eval), Some(comment)) => format!("[%eval {eval}] {comment}"),
+                (Some(eval), None) => format!("[%eval {eval}]"),
+                (None, Some(comment)) => comment,
+                (None, None) => continue,
+            };
+            write!(f, "{{{annotation}}} ")?;
+        }
+        writeln!(f, "{}", self.tag("Result").unwrap_or("*"))
+    }
+}
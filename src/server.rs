@@ -0,0 +1,256 @@
+use super::*;
+use position::{Notation, OpeningBook};
+use serde_json::{json, Value};
+use solver::Solver;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// json api over http, every endpoint takes pos (1-based moves unless
+// notation=zero|letters|key is given) and an optional weak=true, both
+// percent-encoded or not:
+//
+//   GET /solve?pos=4455        {"pos":"4455","weak":false,"score":18,"nodes":3}
+//   GET /analyse?pos=4455      {"pos":"4455","weak":false,"scores":[-4,...],"nodes":..}
+//   GET /bestmove?pos=4455     {"pos":"4455","weak":false,"move":3,"score":18,"nodes":..}
+//
+// errors come back as {"error": "..."} with a 4xx or 5xx status
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    // solver threads, each with its own transposition table
+    pub workers: usize,
+    // entries per worker, see OptimizedTranspoisitionTable::with_size
+    pub table_size: usize,
+    // searches running longer are stopped and answered with 503
+    pub timeout: Duration,
+    // responses kept, 0 disables the cache
+    pub cache_size: usize,
+}
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            workers: 4,
+            table_size: 1_048_583, // smallest prime >= 1 << 20
+            timeout: Duration::from_secs(10),
+            cache_size: 10_000,
+        }
+    }
+}
+
+// least recently used responses, keyed by request
+struct Cache {
+    size: usize,
+    tick: u64,
+    entries: HashMap<String, (Value, u64)>,
+    // tick of the last use to key, oldest first
+    order: BTreeMap<u64, String>,
+}
+impl Cache {
+    fn new(size: usize) -> Self {
+        Self {
+            size,
+            tick: 0,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+    fn get(&mut self, key: &str) -> Option<Value> {
+        self.tick += 1;
+        let (value, used) = self.entries.get_mut(key)?;
+        self.order.remove(used);
+        *used = self.tick;
+        self.order.insert(self.tick, key.to_string());
+        Some(value.clone())
+    }
+    fn put(&mut self, key: String, value: Value) {
+        if self.size == 0 {
+            return;
+        }
+        self.tick += 1;
+        if let Some((_, used)) = self.entries.insert(key.clone(), (value, self.tick)) {
+            self.order.remove(&used);
+        }
+        self.order.insert(self.tick, key);
+        while self.entries.len() > self.size {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+    }
+}
+
+// one solver of the pool with the flag that stops it
+pub struct Worker {
+    solver: Solver,
+    stop: Arc<AtomicBool>,
+}
+impl Worker {
+    pub fn new(book: Arc<OpeningBook>, table_size: usize) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut solver = Solver::with_table_size(book, table_size);
+        solver.set_stop_flag(Arc::clone(&stop));
+        Self { solver, stop }
+    }
+}
+
+pub struct Api {
+    timeout: Duration,
+    cache: Mutex<Cache>,
+}
+
+type Reply = (u16, Value);
+
+fn error(status: u16, msg: impl Into<String>) -> Reply {
+    (status, json!({ "error": msg.into() }))
+}
+
+// a query name or value with its %XX escapes and + decoded, None on an
+// invalid escape
+fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::new();
+    let mut rest = s.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        rest = tail;
+        match b {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex = rest
+                    .get(..2)
+                    .filter(|h| h.iter().all(u8::is_ascii_hexdigit))?;
+                bytes.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+                rest = &rest[2..];
+            }
+            b => bytes.push(b),
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+impl Api {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            timeout: config.timeout,
+            cache: Mutex::new(Cache::new(config.cache_size)),
+        }
+    }
+
+    // status code and json body for a request
+    pub fn handle(&self, worker: &mut Worker, method: &str, url: &str) -> Reply {
+        if method != "GET" {
+            return error(405, format!("method {method} not allowed"));
+        }
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        if !matches!(path, "/solve" | "/analyse" | "/bestmove") {
+            return error(404, format!("no endpoint {path}"));
+        }
+        let params = query
+            .split('&')
+            .filter(|p| !p.is_empty())
+            .map(|p| {
+                let (name, value) = p.split_once('=').unwrap_or((p, ""));
+                Some((percent_decode(name)?, percent_decode(value)?))
+            })
+            .collect::<Option<HashMap<_, _>>>();
+        let Some(params) = params else {
+            return error(400, format!("invalid query {query:?}"));
+        };
+        let param = |name| params.get(name).map(String::as_str);
+        let moves = param("pos").unwrap_or("");
+        let weak = match param("weak") {
+            None | Some("false") | Some("0") => false,
+            Some("true") | Some("1") | Some("") => true,
+            Some(w) => return error(400, format!("invalid weak {w:?}")),
+        };
+        let notation = match param("notation") {
+            None | Some("one") => Notation::OneBased,
+            Some("zero") => Notation::ZeroBased,
+            Some("letters") => Notation::Letters,
+            Some("key") => Notation::Key,
+            Some(n) => return error(400, format!("unknown notation {n:?}")),
+        };
+        let Some(pos) = Position::parse_notation(moves, notation) else {
+            return error(400, format!("invalid position {moves:?}"));
+        };
+
+        let key = format!("{path} {} {weak}", pos.key());
+        if let Some(mut cached) = self.cache.lock().unwrap().get(&key) {
+            cached["pos"] = json!(moves);
+            return (200, cached);
+        }
+        let Some(mut body) = self.search(worker, path, &pos, weak) else {
+            let ms = self.timeout.as_millis();
+            return error(503, format!("no answer within {ms} ms"));
+        };
+        self.cache.lock().unwrap().put(key, body.clone());
+        body["pos"] = json!(moves);
+        (200, body)
+    }
+
+    // None once the timeout stopped the solver
+    fn search(&self, worker: &mut Worker, path: &str, pos: &Position, weak: bool) -> Option<Value> {
+        let (done, wait) = mpsc::channel::<()>();
+        let stop = Arc::clone(&worker.stop);
+        let timeout = self.timeout;
+        let watchdog = thread::spawn(move || {
+            if wait.recv_timeout(timeout) == Err(RecvTimeoutError::Timeout) {
+                stop.store(true, Ordering::Relaxed);
+            }
+        });
+        let solver = &mut worker.solver;
+        let nodes = solver.node_count;
+        let mut body = match path {
+            "/solve" => json!({ "score": solver.solve(pos, weak) }),
+            "/analyse" => json!({ "scores": solver.analyse(pos, weak) }),
+            _ => {
+                let analysis = solver.analysis(pos, weak);
+                let col = analysis.best_move.map(|col| col + 1);
+                json!({ "move": col, "score": analysis.score })
+            }
+        };
+        drop(done);
+        watchdog.join().expect("watchdog thread");
+        worker.stop.store(false, Ordering::Relaxed);
+        if worker.solver.stopped() {
+            return None;
+        }
+        body["weak"] = json!(weak);
+        body["nodes"] = json!(worker.solver.node_count - nodes);
+        Some(body)
+    }
+}
+
+// answers requests on addr until the process is killed
+pub fn serve(addr: &str, book: Arc<OpeningBook>, config: Config) -> io::Result<()> {
+    let server = Arc::new(tiny_http::Server::http(addr).map_err(io::Error::other)?);
+    let api = Arc::new(Api::new(&config));
+    let header =
+        tiny_http::Header::from_bytes("Content-Type", "application/json").expect("valid header");
+    let workers: Vec<_> = (0..config.workers.max(1))
+        .map(|_| {
+            let (server, api, header) = (Arc::clone(&server), Arc::clone(&api), header.clone());
+            let mut worker = Worker::new(Arc::clone(&book), config.table_size);
+            thread::spawn(move || -> io::Result<()> {
+                loop {
+                    let request = server.recv()?;
+                    let method = request.method().as_str().to_string();
+                    let (status, body) = api.handle(&mut worker, &method, request.url());
+                    let response = tiny_http::Response::from_string(body.to_string())
+                        .with_status_code(status)
+                        .with_header(header.clone());
+                    // the client may be gone already
+                    let _ = request.respond(response);
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().expect("worker thread")?;
+    }
+    Ok(())
+}
@@ -1,41 +1,124 @@
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use crate::position::{MoveSorter, OpeningBook, Position};
 use crate::transposition_table::{OptimizedTranspoisitionTable, TranspositionTable};
 
+// highest scoring column of an analyse or analyse_depth vector, ties go
+// to the centre
+pub fn best_move<T: Ord + Copy>(scores: &[Option<T>]) -> Option<usize> {
+    scores
+        .iter()
+        .enumerate()
+        .flat_map(|(col, score)| score.map(|score| (score, col)))
+        .max_by_key(|&(score, col)| (score, std::cmp::Reverse(col.abs_diff(Position::WIDTH / 2))))
+        .map(|(_, col)| col)
+}
+
+// exact score as a depth limited score, beyond any static evaluation
+fn proven(score: isize) -> isize {
+    score + score.signum() * Position::EVAL_BOUND
+}
+
+// result of a depth limited search. Static evaluations stay inside
+// +-Position::EVAL_BOUND, wins and losses found by the search lie beyond
+// it as EVAL_BOUND plus the score, so values order the way they should.
+// exact when no static evaluation went into it, proven draws included
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DepthScore {
+    pub value: isize,
+    pub exact: bool,
+}
+impl DepthScore {
+    fn solved(score: isize) -> Self {
+        Self {
+            value: proven(score),
+            exact: true,
+        }
+    }
+    // the score solve would return, None for guesses
+    pub fn score(&self) -> Option<isize> {
+        self.exact
+            .then(|| self.value - self.value.signum() * Position::EVAL_BOUND)
+    }
+}
+
+// what analysis finds out about a position
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Analysis {
+    pub position: Position,
+    pub weak: bool,
+    // score of the position, None once the game is over
+    pub score: Option<isize>,
+    pub best_move: Option<usize>,
+    pub scores: Vec<Option<isize>>,
+    pub nodes: u64,
+}
+
 pub struct Solver {
     pub node_count: u64,
     column_order: [usize; Position::WIDTH],
-    table: Box<dyn TranspositionTable>,
+    table: Box<dyn TranspositionTable + Send>,
     book: Arc<OpeningBook>,
+    stop: Option<Arc<AtomicBool>>,
+    stopped: bool,
+    // static evaluations by depth limited searches
+    evaluations: u64,
 }
 
+impl Default for Solver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl Solver {
     pub fn new() -> Self {
-        Self {
-            node_count: 0,
-            column_order: [3, 2, 4, 1, 5, 0, 6],
-            table: Box::new(OptimizedTranspoisitionTable::new()),
-            book: Arc::new(OpeningBook::new()),
-        }
+        Self::with_opening_book(Arc::new(OpeningBook::new()))
     }
     pub fn with_opening_book(book: Arc<OpeningBook>) -> Self {
+        Self::with_table_size(book, OptimizedTranspoisitionTable::SIZE)
+    }
+    pub fn with_table_size(book: Arc<OpeningBook>, table_size: usize) -> Self {
         Self {
             node_count: 0,
             column_order: [3, 2, 4, 1, 5, 0, 6],
-            table: Box::new(OptimizedTranspoisitionTable::new()),
+            table: Box::new(OptimizedTranspoisitionTable::with_size(table_size)),
             book,
+            stop: None,
+            stopped: false,
+            evaluations: 0,
         }
     }
     pub fn reset(&mut self) {
         self.node_count = 0;
         self.table.reset()
     }
+    // setting the flag aborts the running search, whatever it returns
+    // afterwards is meaningless until the flag is cleared, see stopped()
+    pub fn set_stop_flag(&mut self, flag: Arc<AtomicBool>) {
+        self.stop = Some(flag);
+    }
+    pub fn stopped(&self) -> bool {
+        self.stopped
+    }
+    fn stop_requested(&self) -> bool {
+        self.stop
+            .as_ref()
+            .is_some_and(|flag| flag.load(Ordering::Relaxed))
+    }
 
     fn negamax(&mut self, pos: Position, mut alpha: isize, mut beta: isize) -> isize {
         assert!(alpha < beta);
         assert!(!pos.has_winning_move());
         self.node_count += 1;
+        if self.node_count.is_multiple_of(4096) && self.stop_requested() {
+            self.stopped = true;
+        }
+        if self.stopped {
+            return alpha;
+        }
 
         let max: isize = match self.table.get(pos.key()) {
             0 => ((Position::HEIGHT * Position::WIDTH) as isize - 1 - (pos.moves as isize)) / 2,
@@ -54,8 +137,8 @@ impl Solver {
             0
         } else if alpha >= beta {
             beta
-        } else if let Some(n) = self.book.get(&pos) {
-            (n as isize) + Position::MIN_SCORE - 1
+        } else if let Some(score) = self.book.score(&pos) {
+            score
         } else {
             let mut moves = MoveSorter::new();
             self.column_order
@@ -70,6 +153,9 @@ impl Solver {
             while let Some(m) = moves.get_next() {
                 let p2 = pos.next_pos_move(m);
                 let score = -self.negamax(p2, -beta, -alpha);
+                if self.stopped {
+                    return alpha;
+                }
 
                 if score >= beta {
                     return score;
@@ -83,8 +169,56 @@ impl Solver {
             alpha
         }
     }
+    // like negamax but guesses with Position::evaluate once depth runs out,
+    // stays away from the table since its scores are not exact
+    fn negamax_depth(
+        &mut self,
+        pos: Position,
+        depth: usize,
+        mut alpha: isize,
+        beta: isize,
+    ) -> isize {
+        self.node_count += 1;
+        if self.node_count.is_multiple_of(4096) && self.stop_requested() {
+            self.stopped = true;
+        }
+        if self.stopped {
+            return alpha;
+        }
+        let next = pos.possible_non_loosing_moves();
+        if next == 0 {
+            return proven(-pos.calc_score());
+        } else if pos.is_draw() {
+            return 0;
+        } else if let Some(score) = self.book.score(&pos) {
+            return proven(score);
+        } else if depth == 0 {
+            self.evaluations += 1;
+            return pos.evaluate();
+        }
+        let mut moves = MoveSorter::new();
+        self.column_order
+            .into_iter()
+            .rev()
+            .flat_map(|c| match next & Position::column_mask(c) {
+                0 => None,
+                n => Some(n),
+            })
+            .for_each(|m| moves.add(m, pos.move_score(m)));
+        while let Some(m) = moves.get_next() {
+            let score = -self.negamax_depth(pos.next_pos_move(m), depth - 1, -beta, -alpha);
+            if self.stopped {
+                return alpha;
+            }
+            if score >= beta {
+                return score;
+            }
+            alpha = alpha.max(score);
+        }
+        alpha
+    }
     fn iterative_deepening(&mut self, pos: &Position, mut min: isize, mut max: isize) -> isize {
-        while min < max {
+        while min < max && !self.stopped {
             let med = match min + (max - min) / 2 {
                 med if med <= 0 && min / 2 < med => min / 2,
                 med if med >= 0 && max / 2 > med => max / 2,
@@ -109,6 +243,7 @@ impl Solver {
         } else {
             (-1, 1)
         };
+        self.stopped = self.stop_requested();
         if pos.has_winning_move() {
             ((Position::WIDTH * Position::HEIGHT + 1 - pos.moves) / 2) as isize
         } else {
@@ -116,6 +251,13 @@ impl Solver {
         }
     }
     pub fn analyse(&mut self, pos: &Position, weak: bool) -> Vec<Option<isize>> {
+        self.stopped = self.stop_requested();
+        if let Some(scores) = self.book.analyse(pos) {
+            return scores
+                .into_iter()
+                .map(|score| score.map(|s| if weak { s.signum() } else { s }))
+                .collect();
+        }
         (0..Position::WIDTH)
             .map(|col| {
                 if !pos.can_play(col) {
@@ -128,4 +270,74 @@ impl Solver {
             })
             .collect()
     }
+    // search cut off after depth plies, for when there is no time to solve
+    pub fn solve_depth(&mut self, pos: &Position, depth: usize) -> DepthScore {
+        self.stopped = self.stop_requested();
+        if pos.has_winning_move() {
+            return DepthScore::solved(
+                ((Position::WIDTH * Position::HEIGHT + 1 - pos.moves) / 2) as isize,
+            );
+        }
+        // pruned lines can't change the value, so without any static
+        // evaluation it is the one of the whole game
+        let evaluations = self.evaluations;
+        let bound = 2 * Position::EVAL_BOUND;
+        let value = self.negamax_depth(pos.clone(), depth, -bound, bound);
+        DepthScore {
+            value,
+            exact: self.evaluations == evaluations && !self.stopped,
+        }
+    }
+    // scores of the columns from the side to move
+    pub fn analyse_depth(&mut self, pos: &Position, depth: usize) -> Vec<Option<DepthScore>> {
+        (0..Position::WIDTH)
+            .map(|col| {
+                if !pos.can_play(col) {
+                    None
+                } else if pos.is_winning_move(col) {
+                    let score = (Position::WIDTH * Position::HEIGHT + 1 - pos.moves) / 2;
+                    Some(DepthScore::solved(score as isize))
+                } else {
+                    let score = self.solve_depth(&pos.next_pos(col), depth);
+                    Some(DepthScore {
+                        value: -score.value,
+                        ..score
+                    })
+                }
+            })
+            .collect()
+    }
+    // analyse with the best move and the score it leads to
+    pub fn analysis(&mut self, pos: &Position, weak: bool) -> Analysis {
+        let nodes = self.node_count;
+        let scores = self.analyse(pos, weak);
+        let best_move = best_move(&scores);
+        Analysis {
+            position: pos.clone(),
+            weak,
+            score: best_move.and_then(|col| scores[col]),
+            best_move,
+            scores,
+            nodes: self.node_count - nodes,
+        }
+    }
+    pub fn best_move(&mut self, pos: &Position, weak: bool) -> Option<usize> {
+        best_move(&self.analyse(pos, weak))
+    }
+    // best moves for both sides until the game ends
+    pub fn principal_variation(&mut self, pos: &Position, weak: bool) -> Vec<usize> {
+        let mut pos = pos.clone();
+        let mut pv = Vec::new();
+        while let Some(col) = self.best_move(&pos, weak) {
+            if self.stopped {
+                break;
+            }
+            pv.push(col);
+            if pos.is_winning_move(col) {
+                break;
+            }
+            pos = pos.next_pos(col);
+        }
+        pv
+    }
 }
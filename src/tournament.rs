@@ -0,0 +1,193 @@
+use super::*;
+use game::{Game, Status};
+use player::AiPlayer;
+use position::OpeningBook;
+use record::GameRecord;
+use rng::Rng;
+use std::fmt;
+use std::io::{self, Write};
+
+// how the start positions of a match are picked
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Openings {
+    // every game from the empty board
+    Empty,
+    // random moves that neither win nor hand the opponent a win
+    Random(usize),
+    // random among the moves the book scores best, shorter where it runs out
+    Book(usize),
+}
+
+// count start positions, the same opening can come up more than once
+pub fn openings(kind: Openings, count: usize, book: &OpeningBook, rng: &mut Rng) -> Vec<Game> {
+    (0..count)
+        .map(|_| {
+            let mut game = Game::new();
+            let plies = match kind {
+                Openings::Empty => 0,
+                Openings::Random(plies) | Openings::Book(plies) => plies,
+            };
+            while game.history().len() < plies {
+                let pos = game.position();
+                let cols: Vec<_> = match kind {
+                    Openings::Book(_) => match book.analyse(&pos) {
+                        Some(scores) => {
+                            let best = scores.iter().flatten().max();
+                            (0..Position::WIDTH)
+                                .filter(|&col| best.is_some() && scores[col].as_ref() == best)
+                                .collect()
+                        }
+                        None => Vec::new(),
+                    },
+                    _ if pos.has_winning_move() => Vec::new(),
+                    _ => {
+                        let next = pos.possible_non_loosing_moves();
+                        (0..Position::WIDTH)
+                            .filter(|&col| next & Position::column_mask(col) != 0)
+                            .collect()
+                    }
+                };
+                let Some(col) = rng.choose(&cols) else {
+                    break;
+                };
+                game.play(col);
+                // openings never decide the game
+                if game.status() != Status::Ongoing {
+                    game.undo();
+                    break;
+                }
+            }
+            game
+        })
+        .collect()
+}
+
+// one finished game of a match
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MatchGame {
+    pub round: usize,
+    // names of player 1 and player 2
+    pub players: [String; 2],
+    // plies of the game that came from the opening
+    pub opening: usize,
+    pub game: Game,
+}
+impl MatchGame {
+    pub fn record(&self) -> GameRecord {
+        let mut record = GameRecord::from_game(&self.game);
+        let tags = [
+            ("Round", self.round.to_string()),
+            ("Player1", self.players[0].clone()),
+            ("Player2", self.players[1].clone()),
+            ("Opening", self.game.moves()[..self.opening].to_string()),
+        ];
+        // ahead of the size and result tags
+        record
+            .tags
+            .splice(0..0, tags.map(|(k, v)| (k.to_string(), v)));
+        record
+    }
+}
+impl fmt::Display for MatchGame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.record())
+    }
+}
+
+// wins, draws and losses of the first engine against the second
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MatchResult {
+    pub names: [String; 2],
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+}
+impl MatchResult {
+    pub fn games(&self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+    // points per game of the first engine, 1 for a win and 0.5 for a draw
+    pub fn score(&self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games().max(1) as f64
+    }
+    // elo difference of the first engine, infinite after a clean sweep
+    pub fn elo(&self) -> f64 {
+        elo(self.score())
+    }
+    // half width of the 95% confidence interval of elo()
+    pub fn elo_error(&self) -> f64 {
+        let n = self.games().max(1) as f64;
+        let s = self.score();
+        let variance = (self.wins as f64 * (1.0 - s).powi(2)
+            + self.draws as f64 * (0.5 - s).powi(2)
+            + self.losses as f64 * s.powi(2))
+            / n;
+        let margin = 1.96 * (variance / n).sqrt();
+        // clamped so the bounds stay finite
+        let clamp = |x: f64| x.clamp(0.5 / n, 1.0 - 0.5 / n);
+        (elo(clamp(s + margin)) - elo(clamp(s - margin))) / 2.0
+    }
+}
+fn elo(score: f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+impl fmt::Display for MatchResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} vs {}: +{} ={} -{} ({:.1}%), elo {:+.0}",
+            self.names[0],
+            self.names[1],
+            self.wins,
+            self.draws,
+            self.losses,
+            100.0 * self.score(),
+            self.elo(),
+        )?;
+        // no error bar to give after a clean sweep
+        match self.elo().is_finite() {
+            true => write!(f, " ± {:.0}", self.elo_error()),
+            false => Ok(()),
+        }
+    }
+}
+
+// plays two games per opening with colours swapped, a moves first in
+// the even rounds, every finished game is written to log
+pub fn run_match(
+    a: &mut dyn AiPlayer,
+    b: &mut dyn AiPlayer,
+    openings: &[Game],
+    mut log: impl Write,
+) -> io::Result<MatchResult> {
+    let mut result = MatchResult {
+        names: [a.name(), b.name()],
+        ..Default::default()
+    };
+    for (round, opening) in openings.iter().flat_map(|o| [o, o]).enumerate() {
+        let a_first = round % 2 == 0;
+        let players: [&mut dyn AiPlayer; 2] = match a_first {
+            true => [&mut *a, &mut *b],
+            false => [&mut *b, &mut *a],
+        };
+        let game = game::play_ai(opening.clone(), players)?;
+        let a_player = if a_first { 1 } else { 2 };
+        match game.status() {
+            Status::Won(p) if p == a_player => result.wins += 1,
+            Status::Won(_) => result.losses += 1,
+            _ => result.draws += 1,
+        }
+        let names = match a_first {
+            true => [result.names[0].clone(), result.names[1].clone()],
+            false => [result.names[1].clone(), result.names[0].clone()],
+        };
+        let record = MatchGame {
+            round: round + 1,
+            players: names,
+            opening: opening.history().len(),
+            game,
+        };
+        writeln!(log, "{record}")?;
+    }
+    Ok(result)
+}
@@ -1,11 +1,19 @@
-use super::*;
-use std::io::Read;
-use std::{error::Error, fmt};
+#[cfg(not(target_arch = "wasm32"))]
+use memmap2::{MmapMut, MmapOptions};
+use std::fmt;
+#[cfg(not(target_arch = "wasm32"))]
+use std::fs::File;
+#[cfg(not(target_arch = "wasm32"))]
+use std::io;
 pub trait TranspositionTable {
     fn put(&mut self, key: u64, val: u8);
     fn get(&self, key: u64) -> u8;
     fn reset(&mut self);
 }
+pub trait BookTable: TranspositionTable + Send + Sync {
+    fn keys(&self) -> &[u8];
+    fn values(&self) -> &[u8];
+}
 #[derive(Debug)]
 pub struct NaiveTranspositionTable {
     table: Vec<Entry>,
@@ -34,6 +42,11 @@ impl TranspositionTable for NaiveTranspositionTable {
     }
 }
 
+impl Default for NaiveTranspositionTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl NaiveTranspositionTable {
     const SIZE: usize = 8388593; //8388593 == 64MB
 
@@ -52,6 +65,11 @@ impl NaiveTranspositionTable {
 pub struct Entry {
     key_val: u64,
 }
+impl Default for Entry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl Entry {
     pub fn create(key: u64, val: u8) -> Self {
         let key_val = (key << 8) | val as u64;
@@ -89,12 +107,12 @@ pub struct OptimizedTranspoisitionTable {
 
 impl TranspositionTable for OptimizedTranspoisitionTable {
     fn put(&mut self, key: u64, val: u8) {
-        let i = Self::index(key);
+        let i = self.index(key);
         self.keys[i] = key as u32;
         self.values[i] = val;
     }
     fn get(&self, key: u64) -> u8 {
-        let i = Self::index(key);
+        let i = self.index(key);
         if key as u32 == self.keys[i] {
             self.values[i]
         } else {
@@ -103,19 +121,49 @@ impl TranspositionTable for OptimizedTranspoisitionTable {
     }
     fn reset(&mut self) {}
 }
+impl Default for OptimizedTranspoisitionTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl OptimizedTranspoisitionTable {
-    const SIZE: usize = (1 << 23) + 9;
+    pub const SIZE: usize = (1 << 23) + 9; // prime
+    pub const MIN_SIZE: usize = 131101; // smallest prime >= 1 << 17
     pub fn new() -> Self {
+        Self::with_size(Self::SIZE)
+    }
+    // size in entries of 5 bytes, rounded up to a prime of at least MIN_SIZE.
+    // Entries only keep the low 32 bits of the 49 bit keys, the index
+    // key % size has to tell apart the rest. That needs a size coprime to
+    // 2^32 and at least 2^49 / 2^32 = 2^17, otherwise positions share an
+    // entry and the solver returns wrong scores
+    pub fn with_size(size: usize) -> Self {
+        let size = next_prime(size.max(Self::MIN_SIZE));
         Self {
-            keys: vec![0; Self::SIZE],
-            values: vec![0; Self::SIZE],
+            keys: vec![0; size],
+            values: vec![0; size],
         }
     }
-    fn index(key: u64) -> usize {
-        (key % Self::SIZE as u64) as usize
+    // entries after the rounding
+    pub fn size(&self) -> usize {
+        self.keys.len()
+    }
+    fn index(&self, key: u64) -> usize {
+        (key % self.keys.len() as u64) as usize
     }
 }
 
+fn next_prime(n: usize) -> usize {
+    let is_prime = |n: usize| {
+        n > 1
+            && (2..)
+                .take_while(|d| d * d <= n)
+                .all(|d| !n.is_multiple_of(d))
+    };
+    (n..).find(|&n| is_prime(n)).expect("a prime")
+}
+
+#[derive(PartialEq, Eq)]
 pub struct BookTranspositionTable {
     pub keys: Vec<u8>,
     pub values: Vec<u8>,
@@ -138,8 +186,14 @@ impl TranspositionTable for BookTranspositionTable {
     }
     fn reset(&mut self) {}
 }
+impl Default for BookTranspositionTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl BookTranspositionTable {
-    pub const SIZE: usize = 16777259;
+    pub const LOG_SIZE: u8 = 24;
+    pub const SIZE: usize = 16777259; // smallest prime >= 1 << LOG_SIZE
     pub fn new() -> Self {
         Self {
             keys: vec![0; Self::SIZE],
@@ -162,3 +216,57 @@ impl BookTranspositionTable {
         (key % Self::SIZE as u64) as usize
     }
 }
+impl BookTable for BookTranspositionTable {
+    fn keys(&self) -> &[u8] {
+        &self.keys
+    }
+    fn values(&self) -> &[u8] {
+        &self.values
+    }
+}
+
+// book file mapped copy-on-write: pages are read lazily and
+// shared between processes, puts stay private to this mapping
+#[cfg(not(target_arch = "wasm32"))]
+pub struct MappedBookTable {
+    map: MmapMut,
+    offset: usize,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl TranspositionTable for MappedBookTable {
+    fn put(&mut self, key: u64, val: u8) {
+        let i = self.offset + BookTranspositionTable::index(key);
+        self.map[i] = key as u8;
+        self.map[i + BookTranspositionTable::SIZE] = val;
+    }
+    fn get(&self, key: u64) -> u8 {
+        assert!(key < (1_u64 << 44));
+        let i = BookTranspositionTable::index(key);
+        if key as u8 == self.keys()[i] {
+            self.values()[i]
+        } else {
+            0
+        }
+    }
+    fn reset(&mut self) {}
+}
+#[cfg(not(target_arch = "wasm32"))]
+impl BookTable for MappedBookTable {
+    fn keys(&self) -> &[u8] {
+        &self.map[self.offset..self.offset + BookTranspositionTable::SIZE]
+    }
+    fn values(&self) -> &[u8] {
+        &self.map[self.offset + BookTranspositionTable::SIZE..]
+    }
+}
+#[cfg(not(target_arch = "wasm32"))]
+impl MappedBookTable {
+    // the caller checks the file holds exactly offset + 2 * SIZE bytes
+    pub fn map(file: &File, offset: usize) -> io::Result<Self> {
+        // SAFETY: the mapping is private, but the book file must not be
+        // truncated by another process while it is mapped
+        let map = unsafe { MmapOptions::new().map_copy(file)? };
+        Ok(Self { map, offset })
+    }
+}
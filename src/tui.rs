@@ -0,0 +1,314 @@
+use super::*;
+use game::{Game, Status};
+use position::OpeningBook;
+use ratatui::crossterm::event::{
+    self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind, MouseButton,
+    MouseEventKind,
+};
+use ratatui::crossterm::execute;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Bar, BarChart, BarGroup, Block, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use solver::Solver;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+struct Job {
+    id: u64,
+    pos: Position,
+    weak: bool,
+}
+
+enum Answer {
+    Scores(u64, Vec<Option<isize>>),
+    Pv(u64, Vec<usize>),
+}
+
+// solver thread, the stop flag aborts the job in progress
+// whenever a newer one is queued. the flag is cleared before taking
+// the newest job, so a stop that lands while the queue is drained
+// only reruns the job instead of getting lost
+fn worker(mut solver: Solver, stop: Arc<AtomicBool>, jobs: Receiver<Job>, answers: Sender<Answer>) {
+    solver.set_stop_flag(Arc::clone(&stop));
+    let mut next = jobs.recv().ok();
+    while let Some(mut job) = next.take() {
+        stop.store(false, Ordering::Relaxed);
+        loop {
+            match jobs.try_recv() {
+                Ok(newer) => job = newer,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        }
+        let scores = solver.analyse(&job.pos, job.weak);
+        if solver.stopped() {
+            next = Some(job);
+            continue;
+        }
+        if answers.send(Answer::Scores(job.id, scores)).is_err() {
+            return;
+        }
+        let pv = solver.principal_variation(&job.pos, job.weak);
+        if solver.stopped() {
+            next = Some(job);
+            continue;
+        }
+        if answers.send(Answer::Pv(job.id, pv)).is_err() {
+            return;
+        }
+        next = jobs.recv().ok();
+    }
+}
+
+struct App {
+    game: Game,
+    cursor: usize,
+    weak: bool,
+    job: u64,
+    scores: Option<Vec<Option<isize>>>,
+    pv: Option<Vec<usize>>,
+    board: Rect,
+    stop: Arc<AtomicBool>,
+    jobs: Sender<Job>,
+}
+
+impl App {
+    fn position_changed(&mut self) {
+        self.job += 1;
+        self.scores = None;
+        self.pv = None;
+        if self.game.status() == Status::Ongoing {
+            // queue first, the worker looks for the newer job once stopped
+            let _ = self.jobs.send(Job {
+                id: self.job,
+                pos: self.game.position(),
+                weak: self.weak,
+            });
+            self.stop.store(true, Ordering::Relaxed);
+        }
+    }
+    fn play(&mut self, col: usize) {
+        if self.game.play(col) {
+            self.position_changed();
+        }
+    }
+    fn undo(&mut self) {
+        if self.game.undo().is_some() {
+            self.position_changed();
+        }
+    }
+    fn answer(&mut self, answer: Answer) {
+        match answer {
+            Answer::Scores(id, scores) if id == self.job => self.scores = Some(scores),
+            Answer::Pv(id, pv) if id == self.job => self.pv = Some(pv),
+            _ => {}
+        }
+    }
+    // false once the user quits
+    fn event(&mut self, event: Event) -> bool {
+        match event {
+            Event::Key(key) if key.kind == KeyEventKind::Press => match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return false,
+                KeyCode::Left => self.cursor = self.cursor.saturating_sub(1),
+                KeyCode::Right => self.cursor = (self.cursor + 1).min(Position::WIDTH - 1),
+                KeyCode::Enter | KeyCode::Char(' ') | KeyCode::Down => self.play(self.cursor),
+                KeyCode::Char(c @ '1'..='7') => {
+                    self.cursor = c as usize - '1' as usize;
+                    self.play(self.cursor);
+                }
+                KeyCode::Char('u') | KeyCode::Backspace => self.undo(),
+                KeyCode::Char('w') => {
+                    self.weak = !self.weak;
+                    self.position_changed();
+                }
+                _ => {}
+            },
+            Event::Mouse(mouse) => {
+                let inside = self.board.x < mouse.column
+                    && mouse.column < self.board.right().saturating_sub(1);
+                if inside {
+                    self.cursor = ((mouse.column - self.board.x - 1) / 2) as usize;
+                    self.cursor = self.cursor.min(Position::WIDTH - 1);
+                    if mouse.kind == MouseEventKind::Down(MouseButton::Left) {
+                        self.play(self.cursor);
+                    }
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [left, right] =
+            Layout::horizontal([Constraint::Length(32), Constraint::Min(20)]).areas(frame.area());
+        let [board, bars] =
+            Layout::vertical([Constraint::Length(10), Constraint::Min(6)]).areas(left);
+        let [status, history] =
+            Layout::vertical([Constraint::Length(7), Constraint::Min(3)]).areas(right);
+        self.board = Rect {
+            width: (Position::WIDTH * 2 + 3) as u16,
+            ..board
+        };
+        frame.render_widget(self.board_widget(), self.board);
+        frame.render_widget(self.eval_widget(), bars);
+        frame.render_widget(self.status_widget(), status);
+        frame.render_widget(self.history_widget(), history);
+    }
+
+    fn board_widget(&self) -> Paragraph<'_> {
+        let pos = self.game.position();
+        let mut lines: Vec<Line> = (0..Position::HEIGHT)
+            .rev()
+            .map(|row| {
+                let cells = (0..Position::WIDTH).flat_map(|col| {
+                    let cell = match pos.cell(col, row) {
+                        Some(1) => Span::styled("●", Style::new().fg(Color::Red)),
+                        Some(_) => Span::styled("●", Style::new().fg(Color::Yellow)),
+                        None => Span::raw("·"),
+                    };
+                    [cell, Span::raw(" ")]
+                });
+                Line::from(cells.collect::<Vec<_>>())
+            })
+            .collect();
+        let cols = (0..Position::WIDTH).flat_map(|col| {
+            let style = match col == self.cursor {
+                true => Style::new().add_modifier(Modifier::REVERSED),
+                false => Style::new(),
+            };
+            [Span::styled((col + 1).to_string(), style), Span::raw(" ")]
+        });
+        lines.push(Line::from(cols.collect::<Vec<_>>()));
+        Paragraph::new(lines).block(Block::bordered().title("board"))
+    }
+
+    fn eval_widget(&self) -> BarChart<'_> {
+        // scores shifted so the worst possible loss is an empty bar
+        let offset = -Position::MIN_SCORE + 1;
+        let bars: Vec<Bar> = (0..Position::WIDTH)
+            .map(|col| {
+                let score = self.scores.as_ref().and_then(|s| s[col]);
+                let color = match score {
+                    Some(s) if s > 0 => Color::Green,
+                    Some(0) => Color::Gray,
+                    _ => Color::Red,
+                };
+                Bar::default()
+                    .value(score.map_or(0, |s| (s + offset).max(0) as u64))
+                    .text_value(score.map_or(String::new(), |s| s.to_string()))
+                    .label(Line::from((col + 1).to_string()))
+                    .style(Style::new().fg(color))
+            })
+            .collect();
+        let title = match (&self.scores, self.game.status()) {
+            (_, Status::Won(_) | Status::Draw) => "evaluation",
+            (None, _) => "evaluation (solving…)",
+            (Some(_), _) => "evaluation",
+        };
+        BarChart::default()
+            .block(Block::bordered().title(title))
+            .data(BarGroup::default().bars(&bars))
+            .bar_width(3)
+            .bar_gap(1)
+            .max((Position::MAX_SCORE + offset + 3) as u64)
+    }
+
+    fn status_widget(&self) -> Paragraph<'_> {
+        let pos = self.game.position();
+        let status = match self.game.status() {
+            Status::Won(p) => format!("player {p} won"),
+            Status::Draw => "draw".to_string(),
+            Status::Ongoing => format!("player {} to move", pos.current_player()),
+        };
+        let pv = match (&self.pv, self.game.status()) {
+            (Some(pv), _) => pv.iter().map(|c| (c + 1).to_string()).collect(),
+            (None, Status::Ongoing) => "…".to_string(),
+            (None, _) => String::new(),
+        };
+        let lines = vec![
+            Line::from(status),
+            Line::from(format!(
+                "mode: {}",
+                if self.weak { "weak" } else { "strong" }
+            )),
+            Line::from(format!("pv: {pv}")),
+            Line::from("←/→ 1-7 enter: play  u: undo"),
+            Line::from("w: weak/strong  q: quit"),
+        ];
+        Paragraph::new(lines)
+            .wrap(Wrap { trim: true })
+            .block(Block::bordered().title("analysis"))
+    }
+
+    fn history_widget(&self) -> Paragraph<'_> {
+        let lines: Vec<Line> = self
+            .game
+            .history()
+            .chunks(2)
+            .enumerate()
+            .map(|(i, pair)| {
+                let moves: Vec<_> = pair.iter().map(|c| (c + 1).to_string()).collect();
+                Line::from(format!("{:>3}. {}", i + 1, moves.join(" ")))
+            })
+            .collect();
+        // keep the latest moves in view
+        let height = lines.len().saturating_sub(1) as u16;
+        Paragraph::new(lines)
+            .scroll((height.saturating_sub(8), 0))
+            .block(Block::bordered().title(format!("moves {}", self.game.moves())))
+    }
+}
+
+fn run_app(
+    terminal: &mut DefaultTerminal,
+    mut app: App,
+    answers: Receiver<Answer>,
+) -> io::Result<()> {
+    app.position_changed();
+    loop {
+        terminal.draw(|frame| app.draw(frame))?;
+        if event::poll(Duration::from_millis(50))? && !app.event(event::read()?) {
+            return Ok(());
+        }
+        while let Ok(answer) = answers.try_recv() {
+            app.answer(answer);
+        }
+    }
+}
+
+// full-screen analysis board starting from game
+pub fn run(book: Arc<OpeningBook>, table_size: usize, game: Game, weak: bool) -> io::Result<()> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let (jobs, job_rx) = mpsc::channel();
+    let (answer_tx, answers) = mpsc::channel();
+    let solver = Solver::with_table_size(book, table_size);
+    let worker_stop = Arc::clone(&stop);
+    thread::spawn(move || worker(solver, worker_stop, job_rx, answer_tx));
+
+    let app = App {
+        game,
+        cursor: Position::WIDTH / 2,
+        weak,
+        job: 0,
+        scores: None,
+        pv: None,
+        board: Rect::default(),
+        stop: Arc::clone(&stop),
+        jobs,
+    };
+    let mut terminal = ratatui::init();
+    execute!(io::stdout(), EnableMouseCapture)?;
+    let res = run_app(&mut terminal, app, answers);
+    execute!(io::stdout(), DisableMouseCapture)?;
+    ratatui::restore();
+    // abort the search still running so the worker notices the closed channel
+    stop.store(true, Ordering::Relaxed);
+    res
+}
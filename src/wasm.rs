@@ -0,0 +1,91 @@
+use super::*;
+use position::{Notation, OpeningBook};
+use solver::Solver;
+use std::sync::Arc;
+use wasm_bindgen::prelude::*;
+
+// solver for javascript, positions are 0-based columns as in Notation::ZeroBased,
+// finished games are invalid:
+//
+//   const solver = WasmSolver.withBook(new Uint8Array(bookBytes));
+//   solver.solve("3344", false)     // 18
+//   solver.analyse("3344", false)   // [-4, -3, 18, 2, 2, 18, -3]
+//   solver.bestMove("3344", false)  // 2, 0-based
+
+// the default table is 40 MB, too much for a browser tab
+pub const DEFAULT_TABLE_SIZE: usize = 1_048_583; // smallest prime >= 1 << 20
+
+#[wasm_bindgen]
+pub struct WasmSolver {
+    solver: Solver,
+}
+
+fn parse(moves: &str) -> Result<Position, String> {
+    Position::parse_notation(moves, Notation::ZeroBased)
+        .ok_or_else(|| format!("invalid position: {moves:?}"))
+}
+
+impl WasmSolver {
+    fn with_opening_book(book: OpeningBook, table_size: Option<usize>) -> Self {
+        let table_size = table_size.unwrap_or(DEFAULT_TABLE_SIZE);
+        Self {
+            solver: Solver::with_table_size(Arc::new(book), table_size),
+        }
+    }
+    // the wrappers below without the js types, so they run natively too
+    pub fn try_solve(&mut self, moves: &str, weak: bool) -> Result<isize, String> {
+        Ok(self.solver.solve(&parse(moves)?, weak))
+    }
+    pub fn try_analyse(&mut self, moves: &str, weak: bool) -> Result<Vec<Option<isize>>, String> {
+        Ok(self.solver.analyse(&parse(moves)?, weak))
+    }
+    pub fn try_best_move(&mut self, moves: &str, weak: bool) -> Result<Option<usize>, String> {
+        Ok(self.solver.best_move(&parse(moves)?, weak))
+    }
+}
+
+#[wasm_bindgen]
+impl WasmSolver {
+    // table size in entries of 5 bytes, DEFAULT_TABLE_SIZE if missing, other
+    // sizes are rounded up as in OptimizedTranspoisitionTable::with_size
+    #[wasm_bindgen(constructor)]
+    pub fn new(table_size: Option<usize>) -> Self {
+        Self::with_opening_book(OpeningBook::new(), table_size)
+    }
+    // book file contents, fetched by the page
+    #[wasm_bindgen(js_name = withBook)]
+    pub fn with_book(book: &[u8], table_size: Option<usize>) -> Result<WasmSolver, JsError> {
+        let book = OpeningBook::from_bytes(book).map_err(|e| JsError::new(&e.to_string()))?;
+        Ok(Self::with_opening_book(book, table_size))
+    }
+    pub fn solve(&mut self, moves: &str, weak: bool) -> Result<i32, JsError> {
+        self.try_solve(moves, weak)
+            .map(|score| score as i32)
+            .map_err(|e| JsError::new(&e))
+    }
+    // score of every column, null where the column is full
+    pub fn analyse(&mut self, moves: &str, weak: bool) -> Result<Vec<JsValue>, JsError> {
+        let scores = self
+            .try_analyse(moves, weak)
+            .map_err(|e| JsError::new(&e))?;
+        Ok(scores
+            .into_iter()
+            .map(|score| score.map_or(JsValue::NULL, |s| JsValue::from(s as i32)))
+            .collect())
+    }
+    // 0-based column, undefined once the board is full
+    #[wasm_bindgen(js_name = bestMove)]
+    pub fn best_move(&mut self, moves: &str, weak: bool) -> Result<Option<u32>, JsError> {
+        self.try_best_move(moves, weak)
+            .map(|col| col.map(|col| col as u32))
+            .map_err(|e| JsError::new(&e))
+    }
+    // positions explored since the last reset
+    #[wasm_bindgen(getter)]
+    pub fn nodes(&self) -> f64 {
+        self.solver.node_count as f64
+    }
+    pub fn reset(&mut self) {
+        self.solver.reset()
+    }
+}